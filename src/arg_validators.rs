@@ -1,7 +1,32 @@
 use color::{AlphaColor, ParseError};
 use image::Rgba;
 
-pub(crate) fn validate_blur_edge_factor(value: &str) -> Result<f32, String> {
+use crate::extractor::layout::Canvas;
+use crate::extractor::length::Length;
+use crate::extractor::morphology::Program;
+
+/// Smallest blur sigma, feather width or contour tolerance, smaller values have no effect
+pub(crate) const MIN_LENGTH: f32 = 0.01;
+
+pub(crate) fn validate_blur_edge_factor(value: &str) -> Result<Length, String> {
+    validate_positive_length(value)
+}
+
+pub(crate) fn validate_positive_length(value: &str) -> Result<Length, String> {
+    let length = validate_length(value)?;
+    if let Length::Pixels(number) | Length::Millimeters(number) = length
+        && number < MIN_LENGTH
+    {
+        return Err(format!("Length must be at least {MIN_LENGTH}"));
+    }
+    Ok(length)
+}
+
+pub(crate) fn validate_length(value: &str) -> Result<Length, String> {
+    value.parse::<Length>()
+}
+
+pub(crate) fn validate_blob_size(value: &str) -> Result<f32, String> {
     let num = value
         .parse::<f32>()
         .map_err(|_| "Not a valid floating point number".to_string())?;
@@ -11,6 +36,26 @@ pub(crate) fn validate_blur_edge_factor(value: &str) -> Result<f32, String> {
     Ok(num)
 }
 
+pub(crate) fn validate_aspect_ratio(value: &str) -> Result<f32, String> {
+    let num = value
+        .parse::<f32>()
+        .map_err(|_| "Not a valid floating point number".to_string())?;
+    if num < 1.0 {
+        return Err("Aspect ratio must be 1 or greater".to_string());
+    }
+    Ok(num)
+}
+
+pub(crate) fn validate_fraction(value: &str) -> Result<f32, String> {
+    let num = value
+        .parse::<f32>()
+        .map_err(|_| "Not a valid floating point number".to_string())?;
+    if num <= 0.0 || num > 1.0 {
+        return Err("Number must be greater than 0 and at most 1".to_string());
+    }
+    Ok(num)
+}
+
 pub(crate) fn validate_chroma_key_color(value: &str) -> Result<Rgba<u8>, String> {
     match parse_color(value) {
         Ok(color) => Ok(color),
@@ -18,6 +63,18 @@ pub(crate) fn validate_chroma_key_color(value: &str) -> Result<Rgba<u8>, String>
     }
 }
 
+pub(crate) fn validate_color(value: &str) -> Result<Rgba<u8>, String> {
+    parse_color(value).map_err(|e| e.to_string())
+}
+
+pub(crate) fn validate_canvas(value: &str) -> Result<Canvas, String> {
+    value.parse::<Canvas>()
+}
+
+pub(crate) fn validate_morphology_program(value: &str) -> Result<Program, String> {
+    value.parse::<Program>()
+}
+
 /// Parse a string into a color, with format like this #RRGGBB
 fn parse_color(color: &str) -> Result<Rgba<u8>, ParseError> {
     let color = color::parse_color(color)?;
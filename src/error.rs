@@ -4,6 +4,12 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
+    // -- Arguments
+    /// A length is too small in pixels at the image DPI
+    LengthTooSmall { option: &'static str, pixels: f32 },
+    /// A length is too large in pixels at the image DPI
+    LengthTooLarge { option: &'static str, pixels: f32 },
+
     // -- Externals
     #[from]
     Utf8(std::str::Utf8Error),
@@ -1,34 +1,110 @@
+use std::fs;
 use std::path::PathBuf;
 
-use image::{ImageBuffer, Luma, Rgba};
-use imageproc::{distance_transform::Norm, geometric_transformations::Interpolation};
+use image::{ImageBuffer, Luma, Rgba, RgbaImage};
+use imageproc::geometric_transformations::{Interpolation, Projection};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+use itertools::Itertools; // for join() iterator function
 
-use crate::{Args, Result};
+use crate::{Args, Error, Result, arg_validators};
+use contours::{ContourFormat, Contours, FittedShape};
+use deskew::{Deskew, DeskewMethod};
 use dpi::Dpi;
+use ellipse::{Ellipse, EllipseCanvas};
+use extraction::Blob;
+use filtering::BlobFilter;
 use io::ImageSaver;
-use ocr::TextExtractor;
+use layout::Layout;
+use length::Lengths;
+use metadata::BlobMetadata;
+use morphology::Program;
+use ocr::{OrientationDetector, TextExtractor};
+use ordering::{BlobOrder, Corner};
+use overhang::OverhangPolicy;
+use perspective::Quad;
+use print_size::PhysicalSize;
+use shape::BlobShape;
+use transform::Transform;
 
 mod alpha_channel;
+pub(crate) mod contours;
+mod cropping;
+pub(crate) mod deskew;
 mod detection;
 pub mod dpi;
 mod drawing;
+pub(crate) mod ellipse;
 mod extraction;
+mod filtering;
+mod geometry;
 pub mod io;
+pub(crate) mod layout;
+pub(crate) mod length;
+mod merging;
+mod metadata;
+pub(crate) mod morphology;
 mod ocr;
+pub(crate) mod ordering;
+pub(crate) mod overhang;
+mod perspective;
+mod print_size;
+mod shape;
+mod splitting;
+mod spread;
+mod text_lines;
+mod transform;
+
+/// Margin kept around a blob before rotating it, in blur edge factors for the edge blur
+const BLUR_MARGIN_FACTOR: f32 = 4.0;
+/// Margin kept around a blob before rotating it, in pixels for bicubic interpolation
+const INTERPOLATION_MARGIN: u32 = 2;
+/// Distance beyond the trim edges distance where blobs still count as overhanging, for ragged scan edges
+const OVERHANG_SLACK: f32 = 2.0;
 
 pub struct BlobExtractor {
     file: PathBuf,
     base_path: PathBuf,
     chroma_key_color: Rgba<u8>,
     floodfill_fuzz: f32,
-    trim_edges: u8,
-    grow_edges: u8,
+    lengths: Lengths,
+    custom_morphology: Option<Program>,
+    morphology: Program,
     floodfill_color: Rgba<u8>,
     border_thickness: u32,
     blur_edge_factor: f32,
     pub min_pixels_touching_line: u32,
+    pub(crate) line_suppression_radius: u32,
+    analytic_mask: bool,
+    feather: f32,
     pub max_lines: usize,
+    pub(crate) deskew_method: DeskewMethod,
+    pub(crate) min_deskew_confidence: f32,
+    pub(crate) text_deskew: bool,
     pub max_blob_rotation: f32,
+    fit_ellipse: bool,
+    ellipse_canvas: EllipseCanvas,
+    perspective_correction: bool,
+    inner_crop: bool,
+    inner_crop_inset: u32,
+    auto_orient: bool,
+    snap_print_size: bool,
+    snap_tolerance: f32,
+    layout: Layout,
+    export_contours: Option<ContourFormat>,
+    contour_tolerance: f32,
+    export_transform: bool,
+    split_touching: bool,
+    split_solidity: f32,
+    split_spreads: bool,
+    merge_gap: Option<u32>,
+    merge_min_fill: Option<f32>,
+    blob_filter: BlobFilter,
+    overhang_policy: OverhangPolicy,
+    overhang_margin: u32,
+    blob_order: BlobOrder,
+    row_tolerance: Option<u32>,
+    order_corner: Corner,
     pub save_intermediary_images: bool,
     pub verbose: bool,
     dpi: u32,
@@ -41,19 +117,73 @@ pub struct BlobExtractor {
 impl BlobExtractor {
     pub fn new(file: PathBuf, args: &Args) -> Self {
         let base_path = file.parent().unwrap().join(file.file_stem().unwrap());
-        Self {
+        let mut blob_extractor = Self {
             file,
             base_path,
             chroma_key_color: args.chroma_key_color,
             floodfill_fuzz: args.floodfill_fuzz,
-            trim_edges: args.trim_edges,
-            grow_edges: args.grow_edges,
+            lengths: Lengths {
+                trim_edges: args.trim_edges,
+                grow_edges: args.grow_edges,
+                blur_edge_factor: args.blur_edge_factor,
+                min_pixels_touching_line: args.min_pixels_touching_line,
+                line_suppression_radius: args.line_suppression_radius,
+                feather: args.feather,
+                padding: args.padding,
+                drop_shadow: args.drop_shadow,
+                contour_tolerance: args.contour_tolerance,
+                overhang_margin: args.overhang_margin,
+            },
+            custom_morphology: args.morphology.clone(),
+            // Pixel values below are set from lengths, and set again once the image DPI is known
+            morphology: Program::from_trim_and_grow(0, 0),
             floodfill_color: Rgba([0, 0, 0, 0]), // transparent
             border_thickness: 1,
-            blur_edge_factor: args.blur_edge_factor,
-            min_pixels_touching_line: args.min_pixels_touching_line,
+            blur_edge_factor: 0.0,
+            min_pixels_touching_line: 0,
+            line_suppression_radius: 0,
+            feather: 0.0,
             max_lines: args.max_lines,
+            deskew_method: args.deskew_method,
+            min_deskew_confidence: args.min_deskew_confidence,
+            text_deskew: args.text_deskew,
             max_blob_rotation: args.max_blob_rotation,
+            analytic_mask: args.analytic_mask,
+            fit_ellipse: args.fit_ellipse,
+            ellipse_canvas: args.ellipse_canvas,
+            perspective_correction: args.perspective_correction,
+            inner_crop: args.inner_crop,
+            inner_crop_inset: args.inner_crop_inset,
+            auto_orient: args.auto_orient,
+            snap_print_size: args.snap_print_size,
+            snap_tolerance: args.snap_tolerance,
+            layout: Layout {
+                padding: 0,
+                canvas: args.canvas,
+                background: args.background_color.unwrap_or(Rgba([0, 0, 0, 0])),
+                shadow_offset: None,
+            },
+            export_contours: args.export_contours,
+            contour_tolerance: 0.0,
+            export_transform: args.export_transform,
+            split_touching: args.split_touching,
+            split_solidity: args.split_solidity,
+            split_spreads: args.split_spreads,
+            merge_gap: args.merge_gap,
+            merge_min_fill: args.merge_min_fill,
+            blob_filter: BlobFilter {
+                min_area: args.min_blob_area,
+                max_area: args.max_blob_area,
+                min_size: args.min_blob_size,
+                max_size: args.max_blob_size,
+                min_aspect_ratio: args.min_aspect_ratio,
+                max_aspect_ratio: args.max_aspect_ratio,
+            },
+            overhang_policy: args.overhang_policy,
+            overhang_margin: 0,
+            blob_order: args.blob_order,
+            row_tolerance: args.row_tolerance,
+            order_corner: args.order_corner,
             dpi: args.dpi,
             save_intermediary_images: args.save_intermediary_images,
             verbose: args.verbose,
@@ -61,9 +191,11 @@ impl BlobExtractor {
             ocr_language: args.ocr_language.to_owned(),
             ocr_psm: args.ocr_psm.to_owned(),
             tessdata: args.tessdata.to_owned(),
-        }
+        };
+        blob_extractor.convert_lengths(&Dpi::new(args.dpi));
+        blob_extractor
     }
-    pub fn process(self) -> Result<()> {
+    pub fn process(mut self) -> Result<()> {
         // Open image and maybe get pixel density in dots per inch
         let (image, dpi) = io::open_image(&self.file)?;
 
@@ -72,6 +204,17 @@ impl BlobExtractor {
         if self.verbose {
             println!("{}: using DPI {:?}", self.file.display(), dpi);
         }
+        self.convert_lengths(&dpi);
+        self.check_lengths(&dpi)?;
+        if self.verbose {
+            println!(
+                "{}: using blur edge factor {:.1}, {} pixels touching line, line suppression radius {}",
+                self.file.display(),
+                self.blur_edge_factor,
+                self.min_pixels_touching_line,
+                self.line_suppression_radius
+            );
+        }
 
         let mut image = image.to_rgba8();
 
@@ -81,21 +224,138 @@ impl BlobExtractor {
             println!("{}: dominant color is {}", self.file.display(), color);
         }
 
+        if self.verbose {
+            println!(
+                "{}: using morphology program {}",
+                self.file.display(),
+                self.morphology
+            );
+        }
+
         let saver = ImageSaver::new(&self.base_path, dpi, self.save_intermediary_images);
         self.remove_chroma_key_color_from_image(&mut image, &saver)?;
         let image_mask = self.cleanup_and_extract_image_mask(&mut image, &saver)?;
 
         // Extract individual blobs from the alpha channel
         let blobs = extraction::extract_blobs(&image_mask);
+        let blobs = self.split_touching_blobs(blobs);
+        let blobs = self.merge_fragmented_blobs(blobs);
+        let blobs = self.filter_blobs(blobs, &dpi, &image, &saver)?;
+        let mut blobs = self.skip_overhanging_blobs(blobs, image.width(), image.height());
+        ordering::sort_blobs(
+            &mut blobs,
+            self.blob_order,
+            self.row_tolerance,
+            self.order_corner,
+            image.width(),
+            image.height(),
+        );
+        let blobs = self.split_spread_blobs(blobs, &image);
         println!("{}: found {} blobs", self.file.display(), blobs.len());
+        // Loading the orientation model is slow, so one detector is shared by all blobs
+        let mut orientation_detector = if self.auto_orient {
+            Some(OrientationDetector::new(self.tessdata.as_path())?)
+        } else {
+            None
+        };
+        let mut partial_items = Vec::new();
         for (index, blob) in blobs.iter().enumerate() {
             let blob_number = index as u32 + 1;
-            self.process_blob(blob_number, blob, &image, &saver)?;
+            let mut metadata = BlobMetadata::default();
+            let edges =
+                overhang::touched_edges(blob, image.width(), image.height(), self.overhang_margin);
+            if !edges.is_empty() {
+                let edges = overhang::format_edges(&edges);
+                // Flagging only adds metadata, so it's only worth a message when asked for
+                if self.verbose || self.overhang_policy != OverhangPolicy::Flag {
+                    println!(
+                        "{}: blob {} overhangs the {} edge of the scan",
+                        self.file.display(),
+                        blob_number,
+                        edges
+                    );
+                }
+                metadata.add("Overhang", &edges);
+                partial_items.push(format!("{blob_number}: {edges}"));
+            }
+            self.process_blob(
+                blob_number,
+                blob,
+                &image,
+                &saver,
+                orientation_detector.as_mut(),
+                metadata,
+            )?;
+        }
+        if self.overhang_policy == OverhangPolicy::List && !partial_items.is_empty() {
+            let filename = PathBuf::from(format!("{}-partial.txt", self.base_path.display()));
+            fs::write(&filename, partial_items.join("\n") + "\n")?;
+            println!("{}: saved list of partial items", filename.display());
         }
 
         Ok(())
     }
 
+    /// Convert parameters given as lengths to pixels at the given DPI
+    fn convert_lengths(&mut self, dpi: &Dpi) {
+        let lengths = self.lengths;
+        let edge = |length: length::Length| length.to_pixels(dpi).round() as u8;
+        self.morphology = self.custom_morphology.clone().unwrap_or_else(|| {
+            Program::from_trim_and_grow(edge(lengths.trim_edges), edge(lengths.grow_edges))
+        });
+        self.blur_edge_factor = lengths.blur_edge_factor.to_pixels(dpi);
+        self.min_pixels_touching_line =
+            lengths.min_pixels_touching_line.to_pixels(dpi).round() as u32;
+        self.line_suppression_radius =
+            lengths.line_suppression_radius.to_pixels(dpi).round() as u32;
+        self.feather = lengths.feather.to_pixels(dpi);
+        self.layout.padding = lengths.padding.to_pixels(dpi).round() as u32;
+        self.layout.shadow_offset = lengths
+            .drop_shadow
+            .map(|offset| offset.to_pixels(dpi).round() as u32);
+        self.contour_tolerance = lengths.contour_tolerance.to_pixels(dpi);
+        // Trimming moves blob edges away from the scan edge, so the margin follows the trim distance
+        self.overhang_margin = lengths
+            .overhang_margin
+            .map_or(
+                lengths.trim_edges.to_pixels(dpi) + OVERHANG_SLACK,
+                |margin| margin.to_pixels(dpi),
+            )
+            .round() as u32;
+    }
+
+    /// Check the lengths of enabled features converted to pixels, as millimeters can get too small
+    /// at a low DPI, or too large at a high DPI
+    fn check_lengths(&self, dpi: &Dpi) -> Result<()> {
+        // Morphology sizes are at most 255 pixels
+        if self.custom_morphology.is_none() {
+            let edges = [
+                ("--trim-edges", self.lengths.trim_edges),
+                ("--grow-edges", self.lengths.grow_edges),
+            ];
+            for (option, length) in edges {
+                let pixels = length.to_pixels(dpi).round();
+                if pixels > u8::MAX as f32 {
+                    return Err(Error::LengthTooLarge { option, pixels });
+                }
+            }
+        }
+        // Gaussian blur needs a positive sigma, and Douglas-Peucker a positive tolerance
+        let mut positive = vec![("--blur-edge-factor", self.blur_edge_factor)];
+        if self.analytic_mask {
+            positive.push(("--feather", self.feather));
+        }
+        if self.export_contours.is_some() {
+            positive.push(("--contour-tolerance", self.contour_tolerance));
+        }
+        for (option, pixels) in positive {
+            if pixels < arg_validators::MIN_LENGTH {
+                return Err(Error::LengthTooSmall { option, pixels });
+            }
+        }
+        Ok(())
+    }
+
     /// Decide image output DPI from detected input image metadata
     fn decide_output_dpi(&self, dpi: Option<Dpi>) -> Dpi {
         match dpi {
@@ -155,30 +415,662 @@ impl BlobExtractor {
     ) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>> {
         let mut image_mask = alpha_channel::extract(image);
         saver.save_debug_luma_image_as(&image_mask, "c-mask")?;
-        imageproc::morphology::erode_mut(&mut image_mask, Norm::L1, self.trim_edges);
-        imageproc::morphology::dilate_mut(&mut image_mask, Norm::L1, self.grow_edges);
+        self.morphology.apply(&mut image_mask);
         saver.save_debug_luma_image_as(&image_mask, "d-mask-cleaned")?;
         alpha_channel::replace(image, &image_mask);
         saver.save_debug_rgba_image_as(image, "e-with-mask")?;
         Ok(image_mask)
     }
 
+    /// Split blobs that look like several touching items into one blob per item
+    fn split_touching_blobs(&self, blobs: Vec<Blob>) -> Vec<Blob> {
+        if !self.split_touching {
+            return blobs;
+        }
+        // Parts get labels of their own, after the ones from connected component labelling
+        let mut next_label = blobs.iter().map(|blob| blob.label).max().unwrap_or(0) + 1;
+        let mut result = Vec::new();
+        for blob in blobs {
+            if splitting::looks_merged(&blob, self.split_solidity) {
+                if let Some(parts) = splitting::split_blob(&blob, next_label) {
+                    println!(
+                        "{}: split component {} into {} blobs, components {}",
+                        self.file.display(),
+                        blob.label,
+                        parts.len(),
+                        parts.iter().map(|part| part.label).join(", ")
+                    );
+                    next_label += parts.len() as u32;
+                    result.extend(parts);
+                    continue;
+                }
+                if self.verbose {
+                    println!(
+                        "{}: component {} looks like merged items, but no split was found",
+                        self.file.display(),
+                        blob.label
+                    );
+                }
+            }
+            result.push(blob);
+        }
+        result
+    }
+
+    /// Split blobs with a fold shadow near the middle into their left and right page
+    /// Pages take the place of the spread, so they are numbered in reading order
+    fn split_spread_blobs(
+        &self,
+        blobs: Vec<Blob>,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> Vec<Blob> {
+        if !self.split_spreads {
+            return blobs;
+        }
+        let mut next_label = blobs.iter().map(|blob| blob.label).max().unwrap_or(0) + 1;
+        let mut result = Vec::new();
+        for blob in blobs {
+            let Some(gutter) = spread::find_gutter(&blob, image) else {
+                if self.verbose {
+                    println!(
+                        "{}: component {} has no fold, keeping it whole",
+                        self.file.display(),
+                        blob.label
+                    );
+                }
+                result.push(blob);
+                continue;
+            };
+            match spread::split_pages(&blob, &gutter, next_label) {
+                Some((left_page, right_page)) => {
+                    println!(
+                        "{}: split component {} into two pages at the fold {}, components {} and {}",
+                        self.file.display(),
+                        blob.label,
+                        gutter,
+                        left_page.label,
+                        right_page.label
+                    );
+                    next_label += 2;
+                    result.push(left_page);
+                    result.push(right_page);
+                }
+                None => {
+                    if self.verbose {
+                        println!(
+                            "{}: component {} has a fold {}, but one page would be too small",
+                            self.file.display(),
+                            blob.label,
+                            gutter
+                        );
+                    }
+                    result.push(blob);
+                }
+            }
+        }
+        result
+    }
+
+    /// Merge blobs that lie close together into one blob, as they are probably fragments of the same item
+    /// Parts of a blob that was split are never merged back together
+    fn merge_fragmented_blobs(&self, mut blobs: Vec<Blob>) -> Vec<Blob> {
+        let Some(gap) = self.merge_gap else {
+            return blobs;
+        };
+        'search: loop {
+            for i in 0..blobs.len() {
+                for j in (i + 1)..blobs.len() {
+                    let (a, b) = (&blobs[i], &blobs[j]);
+                    let split_apart = a.parent.is_some() && a.parent == b.parent;
+                    if split_apart || !merging::are_close(a, b, gap) {
+                        continue;
+                    }
+                    let merged = merging::merge(a, b);
+                    if let Some(min_fill) = self.merge_min_fill {
+                        let fill = merging::rectangle_fill(&merged);
+                        if fill < min_fill {
+                            if self.verbose {
+                                println!(
+                                    "{}: not merging component {} with component {}: rectangle fill {:.2} is below {}",
+                                    self.file.display(),
+                                    a.label,
+                                    b.label,
+                                    fill,
+                                    min_fill
+                                );
+                            }
+                            continue;
+                        }
+                    }
+                    println!(
+                        "{}: merged component {} with component {}",
+                        self.file.display(),
+                        a.label,
+                        b.label
+                    );
+                    blobs[i] = merged;
+                    blobs.remove(j);
+                    continue 'search;
+                }
+            }
+            break;
+        }
+        blobs
+    }
+
+    /// Drop blobs outside the configured area, size and aspect ratio limits
+    /// Accepted blobs are outlined in green and rejected blobs in red in the debug overlay
+    fn filter_blobs(
+        &self,
+        blobs: Vec<Blob>,
+        dpi: &Dpi,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        saver: &ImageSaver,
+    ) -> Result<Vec<Blob>> {
+        let mut overlay = saver.is_debugging().then(|| image.clone());
+        let mut accepted = Vec::new();
+        let mut rejected = 0;
+        for blob in blobs {
+            let rejection = self.blob_filter.check(&blob, dpi);
+            if let Some(overlay) = overlay.as_mut() {
+                let color = match rejection {
+                    Some(_) => Rgba([255, 0, 0, 255]),
+                    None => Rgba([0, 255, 0, 255]),
+                };
+                let bounding_box = blob.bounding_box;
+                drawing::draw_border(
+                    overlay,
+                    color,
+                    bounding_box.left(),
+                    bounding_box.top(),
+                    bounding_box.width(),
+                    bounding_box.height(),
+                    3.min(bounding_box.width().min(bounding_box.height()).div_ceil(2)),
+                );
+            }
+            match rejection {
+                Some(reason) => {
+                    rejected += 1;
+                    if self.verbose {
+                        println!(
+                            "{}: rejected component {}: {}",
+                            self.file.display(),
+                            blob.label,
+                            reason
+                        );
+                    }
+                }
+                None => accepted.push(blob),
+            }
+        }
+        if let Some(overlay) = overlay {
+            saver.save_rgba_image_as(&overlay, "f-blobs")?;
+        }
+        if rejected > 0 {
+            println!("{}: rejected {} blobs", self.file.display(), rejected);
+        }
+        Ok(accepted)
+    }
+
+    /// Drop blobs that extend past the edge of the scan, if asked to skip them
+    fn skip_overhanging_blobs(&self, blobs: Vec<Blob>, width: u32, height: u32) -> Vec<Blob> {
+        if self.overhang_policy != OverhangPolicy::Skip {
+            return blobs;
+        }
+        blobs
+            .into_iter()
+            .filter(|blob| {
+                let edges = overhang::touched_edges(blob, width, height, self.overhang_margin);
+                if edges.is_empty() {
+                    return true;
+                }
+                println!(
+                    "{}: skipped component {}, it overhangs the {} edge of the scan",
+                    self.file.display(),
+                    blob.label,
+                    overhang::format_edges(&edges)
+                );
+                false
+            })
+            .collect()
+    }
+
     /// Process a single blob from the image mask
     fn process_blob(
         &self,
         blob_number: u32,
-        blob: &ImageBuffer<Luma<u8>, Vec<u8>>,
+        blob: &Blob,
         image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
         saver: &ImageSaver,
+        orientation_detector: Option<&mut OrientationDetector>,
+        mut metadata: BlobMetadata,
     ) -> Result<()> {
-        saver.save_debug_luma_image_as(blob, format!("mask-{blob_number}-a").as_str())?;
-        let bounding_box = detection::compute_bounding_box(blob, self);
+        let dpi = saver.dpi();
+        if self.verbose {
+            println!(
+                "Blob {blob_number}: component {}, area {} px, centroid {:.1}x{:.1}",
+                blob.label, blob.area, blob.centroid.0, blob.centroid.1
+            );
+        }
+        let measures = shape::measure(blob);
+        let blob_shape = shape::classify(&measures);
+        if self.verbose {
+            println!("Blob {blob_number}: classified as {blob_shape} ({measures})");
+        }
+        metadata.add("Shape", blob_shape);
+        let (scan_width, scan_height) = image.dimensions();
+        if saver.is_debugging() {
+            let mask = blob.to_full_size_mask(scan_width, scan_height);
+            saver.save_debug_luma_image_as(&mask, format!("mask-{blob_number}-a").as_str())?;
+        }
+        // Only work on the part of the scan the blob can reach when rotated, so the work follows
+        // blob size instead of scan size
+        let center = detection::compute_center_from_rectangle(&blob.bounding_box, self);
+        let region = self.rotation_region(&blob.bounding_box, center, scan_width, scan_height);
+        let (left, top) = (region.left() as u32, region.top() as u32);
+        let mask = &blob.to_region_mask(&region);
+        let region_image =
+            &image::imageops::crop_imm(image, left, top, region.width(), region.height())
+                .to_image();
+        let to_region = Transform::translate(-(left as f32), -(top as f32));
+        // Round items have no edges to straighten, so they are never rotated
+        let deskew = match blob_shape {
+            BlobShape::Ellipse => Deskew::none(0.0),
+            BlobShape::Rectangle | BlobShape::Irregular => {
+                deskew::compute_deskew(blob, mask, image, self, saver, blob_number)?
+            }
+        };
+        if self.verbose {
+            println!("Blob {blob_number}: deskew {deskew}");
+        }
+        metadata.add("DeskewAngle", format!("{:.2}", deskew.angle));
+        metadata.add("DeskewConfidence", format!("{:.2}", deskew.confidence));
+        metadata.add(
+            "DeskewMethod",
+            deskew
+                .method
+                .map_or("none".to_string(), |method| method.to_string()),
+        );
+        let deskew_angle = deskew.angle;
+        // Four-sided items are warped to a rectangle instead, which also straightens them
+        let quad = match blob_shape {
+            BlobShape::Rectangle | BlobShape::Irregular if self.perspective_correction => {
+                let quad = perspective::fit_quad(blob, deskew_angle);
+                if quad.is_none() && self.verbose {
+                    println!("Blob {blob_number}: not four-sided, rotating instead");
+                }
+                quad
+            }
+            _ => None,
+        };
+        // Round items get an ellipse fitted, which gives both their orientation and a clean edge
+        let ellipse = match blob_shape {
+            BlobShape::Ellipse if self.fit_ellipse => ellipse::fit(blob),
+            _ => None,
+        };
+        // The quadrilateral is in scan coordinates, so the projection first moves region
+        // coordinates back into the scan
+        let quad = quad.and_then(|quad| {
+            let projection =
+                Projection::translate(left as f32, top as f32).and_then(quad.projection()?);
+            Some((quad, projection, quad.transform()?))
+        });
+        // Every step that moves pixels adds to the transform from scan to output coordinates
+        let mut transform = Transform::identity();
+        let image = match (ellipse, quad) {
+            (Some(ellipse), _) => self.render_ellipse_blob(
+                blob_number,
+                image,
+                &ellipse,
+                &mut transform,
+                &mut metadata,
+            ),
+            (None, Some((quad, projection, rectification))) => {
+                if self.verbose {
+                    println!("Blob {blob_number}: correcting perspective, corners {quad}");
+                }
+                metadata.add("Corners", quad);
+                transform = rectification;
+                self.rectify_blob(blob_number, mask, region_image, &quad, &projection, saver)?
+            }
+            (None, None) => {
+                // Rectangular items can have their edges drawn from the fitted rectangle
+                let outline = match blob_shape {
+                    BlobShape::Rectangle if self.analytic_mask => perspective::fit_rectangle(blob),
+                    _ => None,
+                };
+                let outline = outline.map(|quad| Quad {
+                    corners: quad.corners.map(|p| to_region.apply(p)),
+                });
+                let (image, rotation) = self.rotate_blob(
+                    blob_number,
+                    mask,
+                    region_image,
+                    deskew_angle,
+                    outline.as_ref(),
+                    saver,
+                )?;
+                transform = to_region.then(&rotation);
+                image
+            }
+        };
+        let image = if self.inner_crop {
+            self.inner_crop_blob(blob_number, image, &mut transform)
+        } else {
+            image
+        };
+        let mut te =
+            TextExtractor::new(&self.ocr_language, &self.ocr_psm, self.tessdata.as_path())?;
+        let image = if let Some(detector) = orientation_detector {
+            self.orient_blob(
+                blob_number,
+                image,
+                detector,
+                dpi,
+                &mut transform,
+                &mut metadata,
+            )?
+        } else {
+            image
+        };
+        let image = self.measure_blob(blob_number, image, dpi, &mut transform, &mut metadata);
+        let image = if self.layout.is_plain() {
+            image
+        } else {
+            self.layout.apply(&image, &mut transform)
+        };
+        // Record how output pixels map back to the scan, so annotations can be moved between them
+        if let Some(inverse) = transform.invert() {
+            let source_polygon = inverse.image_outline(image.width(), image.height());
+            if self.verbose {
+                println!(
+                    "Blob {blob_number}: output maps to scan with {}",
+                    if inverse.is_affine() {
+                        "affine transform"
+                    } else {
+                        "perspective transform"
+                    }
+                );
+            }
+            metadata.add("SourceTransform", inverse);
+            metadata.add(
+                "SourcePolygon",
+                source_polygon
+                    .iter()
+                    .map(|(x, y)| format!("{x:.1}x{y:.1}"))
+                    .join(" "),
+            );
+            if self.export_transform {
+                self.export_blob_transform(blob_number, &image, &inverse, &source_polygon)?;
+            }
+        }
+        if let Some(format) = self.export_contours {
+            let fitted = match (ellipse, quad) {
+                (Some(ellipse), _) => Some(FittedShape::Ellipse(ellipse)),
+                (None, Some((quad, _, _))) => Some(FittedShape::Rectangle(quad.corners)),
+                (None, None) => match blob_shape {
+                    BlobShape::Rectangle => perspective::fit_rectangle(blob)
+                        .map(|quad| FittedShape::Rectangle(quad.corners)),
+                    BlobShape::Ellipse => ellipse::fit(blob).map(FittedShape::Ellipse),
+                    BlobShape::Irregular => None,
+                },
+            };
+            let scan = contours::trace(
+                blob,
+                self.contour_tolerance,
+                fitted,
+                scan_width,
+                scan_height,
+            );
+            self.export_blob_contours(blob_number, &scan, &image, &transform, format)?;
+        }
+        saver.save_rgba_image_as(&image, blob_number.to_string().as_str())?;
+        // Perform OCR on blob
+        let input_image_filename =
+            PathBuf::from(format!("{}-{blob_number}.png", self.base_path.display()));
+        let text = if self.save_intermediary_images {
+            te.extract_and_save_text_from_file(
+                &input_image_filename,
+                &PathBuf::from(format!("{}-{blob_number}.txt", self.base_path.display())),
+            )
+        } else {
+            te.extract_text_from_file(&input_image_filename)
+        }?;
+        // Overwrite output image file and include OCR text
+        saver.save_rgba_image_with_text_as(
+            &image,
+            blob_number.to_string().as_str(),
+            &text,
+            &metadata,
+        )?;
+        Ok(())
+    }
+
+    /// Rotate the fitted ellipse upright onto its own canvas, with an elliptical edge
+    fn render_ellipse_blob(
+        &self,
+        blob_number: u32,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        ellipse: &Ellipse,
+        transform: &mut Transform,
+        metadata: &mut BlobMetadata,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut rotation = ellipse.deskew_angle();
+        if rotation.abs() > self.max_blob_rotation {
+            if self.verbose {
+                println!(
+                    "Ignored ellipse rotation {rotation}, it is larger than {}",
+                    self.max_blob_rotation
+                );
+            }
+            rotation = 0.0;
+        }
+        if self.verbose {
+            println!("Blob {blob_number}: fitted ellipse {ellipse}, rotating {rotation:.2}");
+        }
+        metadata.add("Ellipse", ellipse);
+        metadata.add("EllipseRotation", format!("{rotation:.2}"));
+        let image = ellipse::render(image, ellipse, rotation, self.ellipse_canvas);
+        // The ellipse center ends up in the middle of the canvas
+        let (center_x, center_y) = ellipse.center;
+        *transform = Transform::translate(-center_x, -center_y)
+            .then(&Transform::rotate(rotation))
+            .then(&Transform::translate(
+                (image.width() - 1) as f32 / 2.0,
+                (image.height() - 1) as f32 / 2.0,
+            ));
+        image
+    }
+
+    /// Crop the blob to the largest rectangle inside its opaque part, less the inset
+    fn inner_crop_blob(
+        &self,
+        blob_number: u32,
+        image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        transform: &mut Transform,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let rect = cropping::largest_opaque_rectangle(&image)
+            .and_then(|rect| cropping::inset(rect, self.inner_crop_inset));
+        let Some(rect) = rect else {
+            if self.verbose {
+                println!("Blob {blob_number}: no opaque area left for inner crop, keeping edges");
+            }
+            return image;
+        };
+        if self.verbose {
+            println!(
+                "Blob {blob_number}: inner crop to {}x{} at {}x{}",
+                rect.width(),
+                rect.height(),
+                rect.left(),
+                rect.top()
+            );
+        }
+        *transform = transform.then(&Transform::translate(
+            -rect.left() as f32,
+            -rect.top() as f32,
+        ));
+        image::imageops::crop_imm(
+            &image,
+            rect.left() as u32,
+            rect.top() as u32,
+            rect.width(),
+            rect.height(),
+        )
+        .to_image()
+    }
+
+    /// Record the physical size of the blob, after snapping it to a standard print format if enabled
+    fn measure_blob(
+        &self,
+        blob_number: u32,
+        image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        dpi: &Dpi,
+        transform: &mut Transform,
+        metadata: &mut BlobMetadata,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut image = image;
+        if self.snap_print_size {
+            let size = PhysicalSize::of(&image, dpi);
+            match size.nearest_print_size(self.snap_tolerance) {
+                Some(print_size) => {
+                    if self.verbose {
+                        println!("Blob {blob_number}: snapping {size} to {print_size}");
+                    }
+                    let cropped = print_size::crop_to_aspect_ratio(&image, &print_size);
+                    // The crop is centered
+                    *transform = transform.then(&Transform::translate(
+                        -(((image.width() - cropped.width()) / 2) as f32),
+                        -(((image.height() - cropped.height()) / 2) as f32),
+                    ));
+                    image = cropped;
+                    metadata.add("PrintSize", print_size);
+                }
+                None => {
+                    if self.verbose {
+                        println!("Blob {blob_number}: {size} is not near a standard print size");
+                    }
+                }
+            }
+        }
+        let size = PhysicalSize::of(&image, dpi);
+        if self.verbose {
+            println!("Blob {blob_number}: physical size {size}");
+        }
+        metadata.add("WidthMm", format!("{:.1}", size.width));
+        metadata.add("HeightMm", format!("{:.1}", size.height));
+        metadata.add("WidthIn", format!("{:.2}", size.width / 25.4));
+        metadata.add("HeightIn", format!("{:.2}", size.height / 25.4));
+        image
+    }
+
+    /// Turn the blob a multiple of 90 degrees if its text is sideways or upside down
+    fn orient_blob(
+        &self,
+        blob_number: u32,
+        image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        detector: &mut OrientationDetector,
+        dpi: &Dpi,
+        transform: &mut Transform,
+        metadata: &mut BlobMetadata,
+    ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let Some(orientation) = detector.detect(&image, dpi.x) else {
+            if self.verbose {
+                println!("Blob {blob_number}: no text with a clear orientation");
+            }
+            return Ok(image);
+        };
+        if self.verbose {
+            println!(
+                "Blob {blob_number}: rotating {} degrees clockwise to make text upright, confidence {:.2}",
+                orientation.rotation, orientation.confidence
+            );
+        }
+        metadata.add("Orientation", orientation.rotation);
+        metadata.add(
+            "OrientationConfidence",
+            format!("{:.2}", orientation.confidence),
+        );
+        *transform = transform.then(&Transform::quarter_turn(
+            orientation.rotation,
+            image.width(),
+            image.height(),
+        ));
+        Ok(ocr::rotate_clockwise(&image, orientation.rotation))
+    }
+
+    /// Save the simplified outlines of the blob, in scan and output image coordinates
+    fn export_blob_contours(
+        &self,
+        blob_number: u32,
+        scan: &Contours,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        transform: &Transform,
+        format: ContourFormat,
+    ) -> Result<()> {
+        let output = scan.transformed(transform, image.width(), image.height());
+        let base = format!("{}-{blob_number}-contour", self.base_path.display());
+        let mut files = Vec::new();
+        if format.svg() {
+            files.push((PathBuf::from(format!("{base}.svg")), output.to_svg()));
+            files.push((PathBuf::from(format!("{base}-scan.svg")), scan.to_svg()));
+        }
+        if format.json() {
+            let json = contours::to_json(blob_number, scan, &output);
+            files.push((PathBuf::from(format!("{base}.json")), json));
+        }
+        for (filename, contents) in files {
+            fs::write(&filename, contents)?;
+            println!("{}: saved contour", filename.display());
+        }
+        Ok(())
+    }
+
+    /// Save the transform from output image to scan coordinates, its inverse, and the part of the scan
+    /// the output image covers
+    fn export_blob_transform(
+        &self,
+        blob_number: u32,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        inverse: &Transform,
+        source_polygon: &[(f32, f32)],
+    ) -> Result<()> {
+        let scan_to_output = inverse.invert().unwrap_or(Transform::identity());
+        let json = format!(
+            "{{\n  \"blob\": {blob_number},\n  \"output_size\": [{}, {}],\n  \"affine\": {},\n  \"output_to_scan\": {},\n  \"scan_to_output\": {},\n  \"source_polygon\": [{}]\n}}\n",
+            image.width(),
+            image.height(),
+            inverse.is_affine(),
+            inverse.to_json(),
+            scan_to_output.to_json(),
+            contours::json_points(source_polygon)
+        );
+        let filename = PathBuf::from(format!(
+            "{}-{blob_number}-transform.json",
+            self.base_path.display()
+        ));
+        fs::write(&filename, json)?;
+        println!("{}: saved transform", filename.display());
+        Ok(())
+    }
+
+    /// Rotate the blob by the deskew angle around its center, and crop it to its new bounding box
+    /// The mask, image and outline cover the region around the blob, see rotation_region
+    /// Also returns the transform of points from the region to the rotated blob
+    fn rotate_blob(
+        &self,
+        blob_number: u32,
+        mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        deskew_angle: f32,
+        outline: Option<&Quad>,
+        saver: &ImageSaver,
+    ) -> Result<(RgbaImage, Transform)> {
+        let bounding_box = detection::compute_bounding_box(mask, self);
         let center = detection::compute_center_from_rectangle(&bounding_box, self);
-        let deskew_angle =
-            detection::compute_deskew_angle_for_rectangle(blob, self, saver, blob_number)?;
         let black_luma = Luma([0u8]);
         let blob = imageproc::geometric_transformations::rotate(
-            blob,
+            mask,
             point_to_tuple(center),
             angle_to_radians(deskew_angle),
             Interpolation::Bicubic,
@@ -196,7 +1088,7 @@ impl BlobExtractor {
         );
         alpha_channel::replace(&mut image, &blob);
         let bounding_box = detection::compute_bounding_box(&blob, self);
-        let image = image::imageops::crop_imm(
+        let mut image = image::imageops::crop_imm(
             &image,
             bounding_box.left() as u32,
             bounding_box.top() as u32,
@@ -204,23 +1096,92 @@ impl BlobExtractor {
             bounding_box.height(),
         )
         .to_image();
-        saver.save_rgba_image_as(&image, blob_number.to_string().as_str())?;
-        // Perform OCR on blob
-        let mut te =
-            TextExtractor::new(&self.ocr_language, &self.ocr_psm, self.tessdata.as_path())?;
-        let input_image_filename =
-            PathBuf::from(format!("{}-{blob_number}.png", self.base_path.display()));
-        let text = if self.save_intermediary_images {
-            te.extract_and_save_text_from_file(
-                &input_image_filename,
-                &PathBuf::from(format!("{}-{blob_number}.txt", self.base_path.display())),
-            )
-        } else {
-            te.extract_text_from_file(&input_image_filename)
-        }?;
-        // Overwrite output image file and include OCR text
-        saver.save_rgba_image_with_text_as(&image, blob_number.to_string().as_str(), &text)?;
-        Ok(())
+        // Points move like the image: rotated about the center, then cropped
+        let (center_x, center_y) = point_to_tuple(center);
+        let transform = Transform::translate(-center_x, -center_y)
+            .then(&Transform::rotate(deskew_angle))
+            .then(&Transform::translate(
+                center_x - bounding_box.left() as f32,
+                center_y - bounding_box.top() as f32,
+            ));
+        if let Some(outline) = outline {
+            let polygon = outline.corners.map(|p| transform.apply(p));
+            self.clip_to_blob_mask(&mut image, &polygon);
+        }
+        Ok((image, transform))
+    }
+
+    /// Region around the blob that holds it at any rotation, including the blurred edge,
+    /// clipped to the scan
+    fn rotation_region(
+        &self,
+        bounding_box: &Rect,
+        center: Point<u32>,
+        width: u32,
+        height: u32,
+    ) -> Rect {
+        let (center_x, center_y) = (center.x as i32, center.y as i32);
+        let reach_x = (center_x - bounding_box.left()).max(bounding_box.right() + 1 - center_x);
+        let reach_y = (center_y - bounding_box.top()).max(bounding_box.bottom() + 1 - center_y);
+        let radius = (reach_x as f32).hypot(reach_y as f32).ceil() as i32
+            + (self.blur_edge_factor * BLUR_MARGIN_FACTOR).ceil() as i32
+            + INTERPOLATION_MARGIN as i32;
+        let left = (center_x - radius).max(0);
+        let top = (center_y - radius).max(0);
+        let right = (center_x + radius).min(width as i32 - 1);
+        let bottom = (center_y + radius).min(height as i32 - 1);
+        Rect::at(left, top).of_size((right - left + 1) as u32, (bottom - top + 1) as u32)
+    }
+
+    /// Warp the quadrilateral around the blob to a rectangle of the same size
+    /// The mask and image cover the region around the blob, and the projection maps from there
+    fn rectify_blob(
+        &self,
+        blob_number: u32,
+        mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        quad: &Quad,
+        projection: &Projection,
+        saver: &ImageSaver,
+    ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let (width, height) = quad.rectified_size();
+        let mut blob = ImageBuffer::new(width, height);
+        imageproc::geometric_transformations::warp_into(
+            mask,
+            projection,
+            Interpolation::Bicubic,
+            Luma([0u8]),
+            &mut blob,
+        );
+        let blob = imageproc::filter::gaussian_blur_f32(&blob, self.blur_edge_factor);
+        saver.save_debug_luma_image_as(&blob, format!("mask-{blob_number}-d-deskewed").as_str())?;
+        let mut rectified = ImageBuffer::new(width, height);
+        imageproc::geometric_transformations::warp_into(
+            image,
+            projection,
+            Interpolation::Bicubic,
+            Rgba([0, 0, 0, 0]),
+            &mut rectified,
+        );
+        alpha_channel::replace(&mut rectified, &blob);
+        if self.analytic_mask {
+            // The quadrilateral fills the whole canvas, so its edges are the canvas edges
+            let (right, bottom) = (width as f32 - 0.5, height as f32 - 0.5);
+            let polygon = [(-0.5, -0.5), (right, -0.5), (right, bottom), (-0.5, bottom)];
+            self.clip_to_blob_mask(&mut rectified, &polygon);
+        }
+        Ok(rectified)
+    }
+
+    /// Replace the alpha channel with the analytic mask of the outline polygon, within the blurred
+    /// blob mask already in the alpha channel, so missing corners and torn edges stay transparent
+    fn clip_to_blob_mask(
+        &self,
+        image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        polygon: &[(f32, f32)],
+    ) {
+        let mask = drawing::draw_polygon_mask(image.width(), image.height(), polygon, self.feather);
+        alpha_channel::replace_within(image, &mask);
     }
 }
 
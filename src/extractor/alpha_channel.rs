@@ -18,6 +18,12 @@ pub(crate) fn extract(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Lum
     gray_image
 }
 
+/// Brightness of a color pixel, ignoring the alpha channel
+pub(crate) fn luma(pixel: &Rgba<u8>) -> f32 {
+    let [r, g, b, _] = pixel.0;
+    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+}
+
 /// Replace the alpha channel of the specifid color image with the specified grayscale image
 pub(crate) fn replace(
     image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
@@ -31,3 +37,16 @@ pub(crate) fn replace(
         *pixel = Rgba([pixel[0], pixel[1], pixel[2], gray_pixel[0]]);
     }
 }
+
+/// Replace the alpha channel with the grayscale image, but only as far as the current alpha channel,
+/// a blurred blob mask, reaches
+/// The blurred mask is half opaque on the blob edge, so it is doubled to be fully opaque there
+pub(crate) fn replace_within(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    gray_image: &ImageBuffer<Luma<u8>, Vec<u8>>,
+) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let reach = pixel[3].saturating_mul(2);
+        pixel[3] = gray_image.get_pixel(x, y)[0].min(reach);
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+use super::ellipse::Ellipse;
+use super::extraction::Blob;
+use super::geometry;
+use super::transform::Transform;
+
+/// File formats for exported blob contours
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ContourFormat {
+    /// SVG drawings of the outlines, one in scan and one in output image coordinates
+    Svg,
+    /// JSON with the outlines in both scan and output image coordinates
+    Json,
+    /// Both SVG and JSON
+    Both,
+}
+
+impl ContourFormat {
+    pub(crate) fn svg(self) -> bool {
+        matches!(self, ContourFormat::Svg | ContourFormat::Both)
+    }
+
+    pub(crate) fn json(self) -> bool {
+        matches!(self, ContourFormat::Json | ContourFormat::Both)
+    }
+}
+
+/// Shape fitted to a blob
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FittedShape {
+    /// Corners ordered top-left, top-right, bottom-right, bottom-left
+    Rectangle([(f32, f32); 4]),
+    Ellipse(Ellipse),
+}
+
+/// Simplified outlines of a blob and the shape fitted to it, in the coordinates of one image
+#[derive(Debug, Clone)]
+pub(crate) struct Contours {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) outlines: Vec<Vec<(f32, f32)>>,
+    pub(crate) fitted: Option<FittedShape>,
+}
+
+/// Trace the outer outlines of the blob mask and simplify them with Douglas-Peucker,
+/// keeping them within tolerance pixels of the traced outline
+/// Outlines run through the centers of the edge pixels, in scan coordinates
+pub(crate) fn trace(
+    blob: &Blob,
+    tolerance: f32,
+    fitted: Option<FittedShape>,
+    width: u32,
+    height: u32,
+) -> Contours {
+    let (left, top) = (blob.bounding_box.left(), blob.bounding_box.top());
+    let outlines = geometry::outer_contours(&blob.mask)
+        .iter()
+        .map(|contour| imageproc::geometry::approximate_polygon_dp(contour, tolerance as f64, true))
+        .filter(|polygon| polygon.len() >= 3)
+        .map(|polygon| {
+            polygon
+                .iter()
+                .map(|p| ((p.x + left) as f32, (p.y + top) as f32))
+                .collect()
+        })
+        .collect();
+    Contours {
+        width,
+        height,
+        outlines,
+        fitted,
+    }
+}
+
+impl Contours {
+    /// The same contours in another image of the given size, moved there by the transform
+    pub(crate) fn transformed(&self, transform: &Transform, width: u32, height: u32) -> Contours {
+        let outlines = self
+            .outlines
+            .iter()
+            .map(|outline| outline.iter().map(|&p| transform.apply(p)).collect())
+            .collect();
+        let fitted = self.fitted.map(|fitted| match fitted {
+            FittedShape::Rectangle(corners) => {
+                FittedShape::Rectangle(corners.map(|p| transform.apply(p)))
+            }
+            FittedShape::Ellipse(ellipse) => {
+                FittedShape::Ellipse(transform_ellipse(&ellipse, transform))
+            }
+        });
+        Contours {
+            width,
+            height,
+            outlines,
+            fitted,
+        }
+    }
+
+    /// SVG drawing of the outlines in red and the fitted shape in blue, on a canvas the size of the image
+    pub(crate) fn to_svg(&self) -> String {
+        // Pixel centers are at whole coordinates, so the image starts half a pixel before the origin
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"-0.5 -0.5 {0} {1}\">\n",
+            self.width, self.height
+        );
+        for outline in &self.outlines {
+            let path = outline
+                .iter()
+                .enumerate()
+                .map(|(i, (x, y))| format!("{}{x:.2},{y:.2}", if i == 0 { "M" } else { "L" }))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                "  <path d=\"{path} Z\" fill=\"none\" stroke=\"red\" stroke-width=\"1\"/>"
+            );
+        }
+        match self.fitted {
+            Some(FittedShape::Rectangle(corners)) => {
+                let _ = writeln!(
+                    svg,
+                    "  <polygon points=\"{}\" fill=\"none\" stroke=\"blue\" stroke-width=\"1\"/>",
+                    corners
+                        .iter()
+                        .map(|(x, y)| format!("{x:.2},{y:.2}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+            Some(FittedShape::Ellipse(ellipse)) => {
+                let (x, y) = ellipse.center;
+                let _ = writeln!(
+                    svg,
+                    "  <ellipse cx=\"{x:.2}\" cy=\"{y:.2}\" rx=\"{:.2}\" ry=\"{:.2}\" transform=\"rotate({:.2} {x:.2} {y:.2})\" fill=\"none\" stroke=\"blue\" stroke-width=\"1\"/>",
+                    ellipse.semi_major, ellipse.semi_minor, ellipse.angle
+                );
+            }
+            None => {}
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// JSON object with the image size, the outlines as lists of [x, y] points and the fitted shape
+    pub(crate) fn to_json(&self) -> String {
+        let outlines = self
+            .outlines
+            .iter()
+            .map(|outline| format!("[{}]", json_points(outline)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut json = format!(
+            "{{\"width\": {}, \"height\": {}, \"outlines\": [{outlines}]",
+            self.width, self.height
+        );
+        match self.fitted {
+            Some(FittedShape::Rectangle(corners)) => {
+                let _ = write!(json, ", \"rectangle\": [{}]", json_points(&corners));
+            }
+            Some(FittedShape::Ellipse(ellipse)) => {
+                let _ = write!(
+                    json,
+                    ", \"ellipse\": {{\"center\": [{:.2}, {:.2}], \"semi_major\": {:.2}, \"semi_minor\": {:.2}, \"angle\": {:.2}}}",
+                    ellipse.center.0,
+                    ellipse.center.1,
+                    ellipse.semi_major,
+                    ellipse.semi_minor,
+                    ellipse.angle
+                );
+            }
+            None => {}
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// JSON document with the contours of a blob in scan and output image coordinates
+pub(crate) fn to_json(blob_number: u32, scan: &Contours, output: &Contours) -> String {
+    format!(
+        "{{\n  \"blob\": {blob_number},\n  \"scan\": {},\n  \"output\": {}\n}}\n",
+        scan.to_json(),
+        output.to_json()
+    )
+}
+
+/// JSON array of [x, y] points
+pub(crate) fn json_points(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("[{x:.2}, {y:.2}]"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Move the ellipse center and the ends of its axes, exact for rotations, translations and uniform scaling
+fn transform_ellipse(ellipse: &Ellipse, transform: &Transform) -> Ellipse {
+    let (x, y) = ellipse.center;
+    let (sin, cos) = ellipse.angle.to_radians().sin_cos();
+    let center = transform.apply((x, y));
+    let major = transform.apply((x + ellipse.semi_major * cos, y + ellipse.semi_major * sin));
+    let minor = transform.apply((x - ellipse.semi_minor * sin, y + ellipse.semi_minor * cos));
+    let (major_x, major_y) = (major.0 - center.0, major.1 - center.1);
+    let mut angle = major_y.atan2(major_x).to_degrees();
+    // Keep the angle within (-90, 90], like fitted ellipses
+    if angle <= -90.0 {
+        angle += 180.0;
+    } else if angle > 90.0 {
+        angle -= 180.0;
+    }
+    Ellipse {
+        center,
+        semi_major: major_x.hypot(major_y),
+        semi_minor: (minor.0 - center.0).hypot(minor.1 - center.1),
+        angle,
+    }
+}
@@ -0,0 +1,97 @@
+use image::{ImageBuffer, Rgba};
+use imageproc::rect::Rect;
+
+/// Alpha from which a pixel counts as opaque, as blurring the mask leaves 254 inside the blob
+const NEAR_OPAQUE: u8 = 254;
+
+/// Find the largest axis-aligned rectangle where every pixel is opaque
+/// Uses the largest-rectangle-in-histogram method on the run of opaque pixels above each row
+pub(crate) fn largest_opaque_rectangle(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<Rect> {
+    let (width, height) = image.dimensions();
+    let mut heights = vec![0u32; width as usize];
+    let mut best: Option<(u64, Rect)> = None;
+    for y in 0..height {
+        for (x, run) in heights.iter_mut().enumerate() {
+            if image.get_pixel(x as u32, y)[3] >= NEAR_OPAQUE {
+                *run += 1;
+            } else {
+                *run = 0;
+            }
+        }
+        // Columns with increasing run heights, closed when a lower run is found
+        let mut stack: Vec<(u32, u32)> = Vec::new();
+        for x in 0..=width {
+            let run = if x < width { heights[x as usize] } else { 0 };
+            let mut start = x;
+            while let Some(&(left, top_run)) = stack.last() {
+                if top_run < run {
+                    break;
+                }
+                stack.pop();
+                let area = top_run as u64 * (x - left) as u64;
+                if top_run > 0 && best.is_none_or(|(best_area, _)| area > best_area) {
+                    let rect =
+                        Rect::at(left as i32, (y + 1 - top_run) as i32).of_size(x - left, top_run);
+                    best = Some((area, rect));
+                }
+                start = left;
+            }
+            stack.push((start, run));
+        }
+    }
+    best.map(|(_, rect)| rect)
+}
+
+/// Shrink a rectangle by inset pixels on every side
+/// Returns None if nothing is left
+pub(crate) fn inset(rect: Rect, inset: u32) -> Option<Rect> {
+    let width = rect.width().checked_sub(2 * inset).filter(|&w| w > 0)?;
+    let height = rect.height().checked_sub(2 * inset).filter(|&h| h > 0)?;
+    Some(Rect::at(rect.left() + inset as i32, rect.top() + inset as i32).of_size(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Image with the given alpha for each pixel
+    fn image(
+        width: u32,
+        height: u32,
+        alpha: impl Fn(u32, u32) -> u8,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(width, height, |x, y| Rgba([0, 0, 0, alpha(x, y)]))
+    }
+
+    #[test]
+    fn full_square() {
+        let rect = largest_opaque_rectangle(&image(10, 10, |_, _| 255));
+        assert_eq!(rect, Some(Rect::at(0, 0).of_size(10, 10)));
+    }
+
+    #[test]
+    fn l_shape() {
+        // Top-right 6x4 corner is transparent, leaving a 4x10 column and a 10x6 bottom part
+        let alpha = |x, y| if x >= 4 && y < 4 { 0 } else { NEAR_OPAQUE };
+        let rect = largest_opaque_rectangle(&image(10, 10, alpha));
+        assert_eq!(rect, Some(Rect::at(0, 4).of_size(10, 6)));
+    }
+
+    #[test]
+    fn single_pixel() {
+        let alpha = |x, y| {
+            if (x, y) == (2, 3) {
+                255
+            } else {
+                NEAR_OPAQUE - 1
+            }
+        };
+        let rect = largest_opaque_rectangle(&image(5, 5, alpha));
+        assert_eq!(rect, Some(Rect::at(2, 3).of_size(1, 1)));
+    }
+
+    #[test]
+    fn transparent() {
+        assert_eq!(largest_opaque_rectangle(&image(5, 5, |_, _| 0)), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::f32::consts::PI;
+use std::fmt;
+
+use clap::ValueEnum;
+use image::ImageBuffer;
+use image::Luma;
+use image::Rgba;
+
+use super::detection;
+use super::detection::{LineDeskew, OutlineDeskew};
+use super::extraction::Blob;
+use super::geometry;
+use super::io::ImageSaver;
+use super::text_lines;
+use crate::{BlobExtractor, Result};
+
+/// Line angles spread out by this many degrees (circular standard deviation) get no confidence
+const SPREAD_LIMIT: f32 = 2.0;
+/// Hough and outline angles this many degrees apart get no confidence for agreement
+const AGREEMENT_LIMIT: f32 = 2.0;
+
+/// How to find the angle that straightens a blob
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum DeskewMethod {
+    /// Mean angle of the strongest lines found with the Hough transform
+    Hough,
+    /// Angle of the minimum-area rectangle enclosing the blob outline
+    MinAreaRect,
+    /// Use both, and choose the one with the highest confidence
+    Auto,
+    /// Angle of the text lines, for documents without usable edges
+    TextLines,
+}
+
+impl fmt::Display for DeskewMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeskewMethod::Hough => write!(f, "hough"),
+            DeskewMethod::MinAreaRect => write!(f, "min-area-rect"),
+            DeskewMethod::Auto => write!(f, "auto"),
+            DeskewMethod::TextLines => write!(f, "text-lines"),
+        }
+    }
+}
+
+/// The chosen deskew angle, how much we trust it and which method found it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deskew {
+    pub(crate) angle: f32,
+    pub(crate) confidence: f32,
+    /// None when the blob is not rotated
+    pub(crate) method: Option<DeskewMethod>,
+}
+
+impl Deskew {
+    /// Leave the blob as it is
+    pub(crate) fn none(confidence: f32) -> Self {
+        Self {
+            angle: 0.0,
+            confidence,
+            method: None,
+        }
+    }
+}
+
+impl fmt::Display for Deskew {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.method {
+            Some(method) => write!(
+                f,
+                "angle {:.2} using {method}, confidence {:.2}",
+                self.angle, self.confidence
+            ),
+            None => write!(f, "no rotation, confidence {:.2}", self.confidence),
+        }
+    }
+}
+
+/// Compute deskew angle using the configured method, falling back to the other method or to no
+/// rotation when the confidence is below the configured minimum
+/// The outline uses the cropped blob mask, while Hough uses the mask of the region around the blob,
+/// which leaves room for the edges; angles don't depend on where the mask starts
+pub(crate) fn compute_deskew(
+    blob: &Blob,
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    config: &BlobExtractor,
+    saver: &ImageSaver,
+    blob_number: u32,
+) -> Result<Deskew> {
+    let outline = detection::compute_deskew_angle_from_outline(&blob.mask, blob.area, config);
+    let mut lines = match config.deskew_method {
+        DeskewMethod::MinAreaRect | DeskewMethod::TextLines => None,
+        DeskewMethod::Hough | DeskewMethod::Auto => {
+            detection::compute_deskew_angle_for_rectangle(mask, config, saver, blob_number)?
+        }
+    };
+    let primary = match config.deskew_method {
+        DeskewMethod::TextLines => estimate_from_text(blob, image),
+        method => estimate(method, lines.as_ref(), outline.as_ref()),
+    };
+    let confidence = primary.map_or(0.0, |deskew| deskew.confidence);
+    let deskew = if confidence >= config.min_deskew_confidence {
+        primary
+    } else {
+        // Hough lines are only computed on demand, as they are expensive
+        if lines.is_none() && config.deskew_method != DeskewMethod::Hough {
+            lines =
+                detection::compute_deskew_angle_for_rectangle(mask, config, saver, blob_number)?;
+        }
+        let fallback = estimate(DeskewMethod::Auto, lines.as_ref(), outline.as_ref())
+            .filter(|deskew| deskew.confidence >= config.min_deskew_confidence);
+        // Without usable edges, documents can still be straightened by their text lines
+        let fallback = match fallback {
+            None if config.text_deskew && config.deskew_method != DeskewMethod::TextLines => {
+                estimate_from_text(blob, image)
+                    .filter(|deskew| deskew.confidence >= config.min_deskew_confidence)
+            }
+            fallback => fallback,
+        };
+        if config.verbose {
+            match fallback {
+                Some(fallback) => println!(
+                    "Deskew confidence {confidence:.2} is too low, falling back to {fallback}"
+                ),
+                None => println!(
+                    "Deskew confidence {confidence:.2} is too low, and no fallback was better"
+                ),
+            }
+        }
+        fallback
+    };
+    let deskew = deskew.unwrap_or(Deskew::none(confidence));
+    if deskew.angle.abs() > config.max_blob_rotation {
+        if config.verbose {
+            println!(
+                "Ignored deskew angle {}, it is larger than {}",
+                deskew.angle, config.max_blob_rotation
+            );
+        }
+        return Ok(Deskew::none(deskew.confidence));
+    }
+    Ok(deskew)
+}
+
+/// Score the estimate of a single edge method, or pick the most confident one for auto
+/// Text lines are estimated separately, as they need the image instead of the edges
+fn estimate(
+    method: DeskewMethod,
+    lines: Option<&LineDeskew>,
+    outline: Option<&OutlineDeskew>,
+) -> Option<Deskew> {
+    let from_lines = || {
+        lines.map(|lines| Deskew {
+            angle: lines.angle,
+            confidence: score_lines(lines, outline),
+            method: Some(DeskewMethod::Hough),
+        })
+    };
+    let from_outline = || {
+        outline.map(|outline| Deskew {
+            angle: outline.angle,
+            confidence: score_outline(outline, lines),
+            method: Some(DeskewMethod::MinAreaRect),
+        })
+    };
+    match method {
+        DeskewMethod::Hough => from_lines(),
+        DeskewMethod::MinAreaRect => from_outline(),
+        DeskewMethod::Auto => match (from_lines(), from_outline()) {
+            (Some(a), Some(b)) => Some(if a.confidence >= b.confidence { a } else { b }),
+            (a, b) => a.or(b),
+        },
+        DeskewMethod::TextLines => None,
+    }
+}
+
+/// Deskew from the angle of the text lines in the blob
+fn estimate_from_text(blob: &Blob, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<Deskew> {
+    text_lines::estimate(blob, image).map(|text| Deskew {
+        angle: -text.angle,
+        confidence: text.confidence,
+        method: Some(DeskewMethod::TextLines),
+    })
+}
+
+/// Confidence of a Hough angle (0-1), from the spread of the line angles, how much of the
+/// outline the lines cover, and how well the angle agrees with the outline angle
+fn score_lines(lines: &LineDeskew, outline: Option<&OutlineDeskew>) -> f32 {
+    // Circular standard deviation of the folded angles, scaled back from the 4x circle
+    let spread = (-2.0 * lines.agreement.max(f32::EPSILON).ln())
+        .sqrt()
+        .to_degrees()
+        / 4.0;
+    let spread_score = (1.0 - spread / SPREAD_LIMIT).clamp(0.0, 1.0);
+    let Some(outline) = outline else {
+        return spread_score;
+    };
+    let coverage_score = if outline.perimeter > 0.0 {
+        (lines.support as f32 / outline.perimeter).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let agreement_score = agreement(lines.angle, outline.angle);
+    (spread_score + coverage_score + agreement_score) / 3.0
+}
+
+/// Confidence of an outline angle (0-1), from how rectangular the blob is and, if there are
+/// Hough lines, how well the angle agrees with them
+fn score_outline(outline: &OutlineDeskew, lines: Option<&LineDeskew>) -> f32 {
+    // A rectangle fills its minimum-area rectangle completely, an ellipse only pi/4 of it
+    let fill_score = ((outline.fill - PI / 4.0) / (1.0 - PI / 4.0)).clamp(0.0, 1.0);
+    match lines {
+        Some(lines) => (2.0 * fill_score + agreement(outline.angle, lines.angle)) / 3.0,
+        None => fill_score,
+    }
+}
+
+/// How well two deskew angles agree (0-1)
+fn agreement(a: f32, b: f32) -> f32 {
+    let difference = geometry::fold_angle(a - b).abs();
+    (1.0 - difference / AGREEMENT_LIMIT).clamp(0.0, 1.0)
+}
@@ -5,13 +5,22 @@ use image::Luma;
 use image::Pixel; // for to_rgb() method
 use image::Rgba;
 use imageproc::hough::LineDetectionOptions;
+use imageproc::hough::PolarLine;
 use imageproc::point::Point;
 use imageproc::rect::Rect;
 use itertools::Itertools; // for sorted() and join() iterator function
 
+use super::geometry;
 use super::io::ImageSaver;
 use crate::{BlobExtractor, Result};
 
+/// Edge pixels within this distance of a Hough line (pixels) are used to refine its angle
+const LINE_REFINE_DISTANCE: f32 = 2.0;
+/// Number of refitting rounds when refining a Hough line
+const LINE_REFINE_ITERATIONS: usize = 3;
+/// Minimum number of edge pixels needed to refine a Hough line
+const LINE_REFINE_MIN_POINTS: usize = 10;
+
 /// Compute bounding box from grayscale image, any non-black color is considered part of the bounding box
 pub(crate) fn compute_bounding_box(
     image: &ImageBuffer<Luma<u8>, Vec<u8>>,
@@ -58,13 +67,32 @@ pub(crate) fn compute_center_from_rectangle(rect: &Rect, config: &BlobExtractor)
     center
 }
 
+/// Deskew angle found from Hough lines, with the evidence behind it
+pub(crate) struct LineDeskew {
+    pub(crate) angle: f32,
+    /// Resultant length of the folded line angles (1 when all lines agree)
+    pub(crate) agreement: f32,
+    /// Number of edge pixels along the lines
+    pub(crate) support: usize,
+}
+
+/// Deskew angle found from the minimum-area rectangle around the blob outline
+pub(crate) struct OutlineDeskew {
+    pub(crate) angle: f32,
+    /// Blob area versus rectangle area (0-1)
+    pub(crate) fill: f32,
+    /// Perimeter of the convex hull of the blob
+    pub(crate) perimeter: f32,
+}
+
 /// Compute the deskew angle for a rectangular blob
+/// Returns None if no lines with enough pixels touching them were found
 pub(crate) fn compute_deskew_angle_for_rectangle(
     image: &ImageBuffer<Luma<u8>, Vec<u8>>,
     config: &BlobExtractor,
     saver: &ImageSaver,
     blob_number: u32,
-) -> Result<f32> {
+) -> Result<Option<LineDeskew>> {
     // Detect edges in image
     // NB: I have no idea how low/high thresholds work, but a value of 1.0 for both seems to do the trick
     let mut image = imageproc::edges::canny(image, 1.0, 1.0);
@@ -73,55 +101,126 @@ pub(crate) fn compute_deskew_angle_for_rectangle(
     // Find lines matching edges
     let options = LineDetectionOptions {
         vote_threshold: config.min_pixels_touching_line, // understood as number of pixels that should be on the line
-        suppression_radius: 50,
+        suppression_radius: config.line_suppression_radius,
     };
     let mut lines = imageproc::hough::detect_lines(&image, options);
     if lines.is_empty() {
-        return Ok(0.0);
+        if config.verbose {
+            println!("No lines detected for deskew");
+        }
+        return Ok(None);
     }
     lines.truncate(config.max_lines);
+
+    // Hough only votes in whole degrees, so refine each line from the edge pixels along it
+    let edge_points: Vec<(f32, f32)> = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] > 0)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    let (line_angles, line_supports): (Vec<f32>, Vec<usize>) = lines
+        .iter()
+        .map(|line| refine_line_angle(&edge_points, line))
+        .unzip();
+    if config.verbose {
+        println!(
+            "Refined line angles: {}",
+            line_angles.iter().map(|a| format!("{a:.2}")).join(", ")
+        );
+    }
+
     let grey_luma = Luma([128u8]);
     imageproc::hough::draw_polar_lines_mut(&mut image, &lines[..], grey_luma);
     saver.save_debug_luma_image_as(&image, format!("mask-{blob_number}-c-lines").as_str())?;
 
-    // Rotate lines so that they all point in the same direction
-    // Sort all the values from low to high
-    // PolarLines angles are between 0-180 degrees
-    let angles: Vec<i32> = lines
+    // Fold all line angles into (-45, 45], so horizontal and vertical edges point the same way
+    // and average them on the circle, as angles just below 45 and just above -45 are neighbours
+    let folded: Vec<f32> = line_angles
         .iter()
-        .map(|pl| pl.angle_in_degrees as i32)
-        .map(|a| {
-            if a > config.max_blob_rotation as i32 {
-                a - 90
-            } else {
-                a
-            }
-        })
-        .map(|a| {
-            if a > config.max_blob_rotation as i32 {
-                a - 90
-            } else {
-                a
-            }
-        })
-        .sorted()
+        .map(|&a| geometry::fold_angle(a))
         .collect();
-
-    // Find median angle (mean value between the two in the middle if an even number of lines)
-    let mid = angles.len() / 2;
-    let angle: f32 = if angles.len() % 2 == 0 {
-        (angles[mid - 1] as f32 + angles[mid] as f32) / 2.0
-    } else {
-        angles[mid] as f32
-    };
+    let (angle, agreement) = geometry::folded_circular_mean(&folded);
 
     // Invert angle so that the returned value can be used to straighten
-    let inverted_angle = angle * -1.0;
+    let inverted_angle = -angle;
     if config.verbose {
         println!("Computed deskew angle: {inverted_angle}");
     }
 
-    Ok(inverted_angle)
+    Ok(Some(LineDeskew {
+        angle: inverted_angle,
+        agreement,
+        support: line_supports.iter().sum(),
+    }))
+}
+
+/// Compute the deskew angle from the minimum-area rectangle enclosing the convex hull of the blob
+pub(crate) fn compute_deskew_angle_from_outline(
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    area: u32,
+    config: &BlobExtractor,
+) -> Option<OutlineDeskew> {
+    let hull = geometry::outline_hull(mask);
+    let rect = geometry::min_area_rect(&hull)?;
+    let perimeter = geometry::polygon_perimeter(&hull);
+    let fill = (area as f32 / rect.area()).min(1.0);
+    // Invert angle so that the returned value can be used to straighten
+    let inverted_angle = -rect.angle;
+    if config.verbose {
+        println!(
+            "Computed deskew angle from minimum-area rectangle: {inverted_angle} (fill {fill:.2})"
+        );
+    }
+    Some(OutlineDeskew {
+        angle: inverted_angle,
+        fill,
+        perimeter,
+    })
+}
+
+/// Refine the angle of a Hough line to sub-degree precision
+/// Fits a line (total least squares) through the edge points close to the detected line and
+/// returns the angle of its normal in degrees, using the same convention as PolarLine,
+/// together with the number of edge points used in the fit
+fn refine_line_angle(edge_points: &[(f32, f32)], line: &PolarLine) -> (f32, usize) {
+    let mut angle = line.angle_in_degrees as f32;
+    let mut r = line.r;
+    let mut support = 0;
+    for _ in 0..LINE_REFINE_ITERATIONS {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let points: Vec<&(f32, f32)> = edge_points
+            .iter()
+            .filter(|(x, y)| (x * cos + y * sin - r).abs() <= LINE_REFINE_DISTANCE)
+            .collect();
+        if points.len() < LINE_REFINE_MIN_POINTS {
+            break;
+        }
+        support = points.len();
+        let n = points.len() as f32;
+        let mean_x = points.iter().map(|p| p.0).sum::<f32>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f32>() / n;
+        let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+        for (x, y) in points {
+            let (dx, dy) = (x - mean_x, y - mean_y);
+            sxx += dx * dx;
+            syy += dy * dy;
+            sxy += dx * dy;
+        }
+        // Direction of the principal axis, the normal is perpendicular to it
+        let direction = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+        let mut normal = direction.to_degrees() + 90.0;
+        // Keep the normal pointing the same way as the previous estimate
+        while normal - angle > 90.0 {
+            normal -= 180.0;
+        }
+        while angle - normal > 90.0 {
+            normal += 180.0;
+        }
+        angle = normal;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        r = mean_x * cos + mean_y * sin;
+    }
+    (angle, support)
 }
 
 /// Find the color that occurs the most in the specified image
@@ -3,7 +3,7 @@ pub mod decoder;
 use png::{PixelDimensions, Unit};
 
 /// Pixel density in inches
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Dpi {
     pub(crate) x: u32,
     pub(crate) y: u32,
@@ -44,6 +44,23 @@ impl Dpi {
         // 1 inch = 39.37 cm
         (self.y as f32 * 39.37) as u32
     }
+
+    /// Convert horizontal length in pixels to millimeters
+    pub fn x_to_millimeters(&self, pixels: u32) -> f32 {
+        // 1 inch = 25.4 mm
+        pixels as f32 / self.x as f32 * 25.4
+    }
+    /// Convert vertical length in pixels to millimeters
+    pub fn y_to_millimeters(&self, pixels: u32) -> f32 {
+        // 1 inch = 25.4 mm
+        pixels as f32 / self.y as f32 * 25.4
+    }
+
+    /// Convert length in millimeters to pixels, using the mean of both resolutions
+    pub fn millimeters_to_pixels(&self, millimeters: f32) -> f32 {
+        // 1 inch = 25.4 mm
+        millimeters / 25.4 * (self.x + self.y) as f32 / 2.0
+    }
 }
 
 /// For easily converting into PNG PixelDimensions
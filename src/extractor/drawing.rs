@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
 use image::ImageBuffer;
+use image::Luma;
 use image::Rgba;
 use imageproc::rect::Rect;
 
@@ -63,3 +64,41 @@ pub(crate) fn flood_fill(
         }
     }
 }
+
+/// Draw a convex polygon as a grayscale mask, anti-aliased across its edges over feather pixels
+/// Pixel centers are at whole coordinates, and the corners may be in either winding order
+pub(crate) fn draw_polygon_mask(
+    width: u32,
+    height: u32,
+    polygon: &[(f32, f32)],
+    feather: f32,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let count = polygon.len() as f32;
+    let center = polygon.iter().fold((0.0, 0.0), |(sx, sy), (x, y)| {
+        (sx + x / count, sy + y / count)
+    });
+    // Outward unit normal and offset of each edge, so the signed distance is n.p - offset
+    let edges: Vec<(f32, f32, f32)> = (0..polygon.len())
+        .filter_map(|index| {
+            let (ax, ay) = polygon[index];
+            let (bx, by) = polygon[(index + 1) % polygon.len()];
+            let length = (bx - ax).hypot(by - ay);
+            if length <= 0.0 {
+                return None;
+            }
+            let (mut nx, mut ny) = ((by - ay) / length, (ax - bx) / length);
+            if nx * (center.0 - ax) + ny * (center.1 - ay) > 0.0 {
+                (nx, ny) = (-nx, -ny);
+            }
+            Some((nx, ny, nx * ax + ny * ay))
+        })
+        .collect();
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (x, y) = (x as f32, y as f32);
+        let distance = edges
+            .iter()
+            .map(|(nx, ny, offset)| nx * x + ny * y - offset)
+            .fold(f32::MIN, f32::max);
+        Luma([((0.5 - distance / feather).clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
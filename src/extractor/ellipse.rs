@@ -0,0 +1,150 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use image::{ImageBuffer, Luma, Rgba};
+use imageproc::geometric_transformations::{Interpolation, Projection};
+
+use super::alpha_channel;
+use super::extraction::Blob;
+use super::geometry;
+
+/// Ellipses with minor versus major axis above this are treated as circles, which have no orientation
+const ROUND_AXIS_RATIO: f32 = 0.97;
+
+/// Size of the output image for elliptical blobs
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum EllipseCanvas {
+    /// Just large enough to hold the ellipse
+    Tight,
+    /// Square, with the longest side of the tight canvas
+    Square,
+}
+
+/// Ellipse in scan coordinates
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ellipse {
+    pub(crate) center: (f32, f32),
+    pub(crate) semi_major: f32,
+    pub(crate) semi_minor: f32,
+    /// Clockwise rotation of the major axis in degrees, within (-90, 90]
+    pub(crate) angle: f32,
+}
+
+impl Ellipse {
+    /// Rotation that turns the axes horizontal and vertical, like a deskew angle
+    /// Circles are left as they are
+    pub(crate) fn deskew_angle(&self) -> f32 {
+        if self.semi_minor / self.semi_major > ROUND_AXIS_RATIO {
+            0.0
+        } else {
+            -geometry::fold_angle(self.angle)
+        }
+    }
+}
+
+impl fmt::Display for Ellipse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}x{:.0} at {:.1}x{:.1}, major axis at {:.2} degrees",
+            2.0 * self.semi_major,
+            2.0 * self.semi_minor,
+            self.center.0,
+            self.center.1,
+            self.angle
+        )
+    }
+}
+
+/// Fit an ellipse with the same area moments as the blob
+/// A filled ellipse has variance a²/4 along each of its semi-axes a
+pub(crate) fn fit(blob: &Blob) -> Option<Ellipse> {
+    if blob.area == 0 {
+        return None;
+    }
+    let (mean_x, mean_y) = (
+        blob.centroid.0 - blob.bounding_box.left() as f32,
+        blob.centroid.1 - blob.bounding_box.top() as f32,
+    );
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for (x, y, pixel) in blob.mask.enumerate_pixels() {
+        if pixel[0] > 0 {
+            let (dx, dy) = (x as f32 - mean_x, y as f32 - mean_y);
+            xx += dx * dx;
+            yy += dy * dy;
+            xy += dx * dy;
+        }
+    }
+    let n = blob.area as f32;
+    let (xx, yy, xy) = (xx / n, yy / n, xy / n);
+    // Eigenvalues of the covariance matrix
+    let mean = (xx + yy) / 2.0;
+    let spread = (((xx - yy) / 2.0).powi(2) + xy * xy).sqrt();
+    let (major, minor) = (mean + spread, (mean - spread).max(0.0));
+    if minor <= 0.0 {
+        return None;
+    }
+    let angle = 0.5 * (2.0 * xy).atan2(xx - yy).to_degrees();
+    Some(Ellipse {
+        center: blob.centroid,
+        semi_major: 2.0 * major.sqrt(),
+        semi_minor: 2.0 * minor.sqrt(),
+        angle,
+    })
+}
+
+/// Rotate the ellipse clockwise by rotation degrees onto a new canvas, with an anti-aliased
+/// elliptical alpha channel computed from the fitted ellipse instead of the blob mask
+pub(crate) fn render(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ellipse: &Ellipse,
+    rotation: f32,
+    canvas: EllipseCanvas,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (a, b) = (ellipse.semi_major, ellipse.semi_minor);
+    let (sin, cos) = (ellipse.angle + rotation).to_radians().sin_cos();
+    // Half extents of the rotated ellipse
+    let half_width = (a * a * cos * cos + b * b * sin * sin).sqrt();
+    let half_height = (a * a * sin * sin + b * b * cos * cos).sqrt();
+    let (width, height) = match canvas {
+        EllipseCanvas::Tight => (
+            (2.0 * half_width).ceil() as u32,
+            (2.0 * half_height).ceil() as u32,
+        ),
+        EllipseCanvas::Square => {
+            let side = (2.0 * half_width.max(half_height)).ceil() as u32;
+            (side, side)
+        }
+    };
+    let (width, height) = (width.max(1), height.max(1));
+    let center_x = (width - 1) as f32 / 2.0;
+    let center_y = (height - 1) as f32 / 2.0;
+
+    let projection = Projection::translate(center_x, center_y)
+        * Projection::rotate(rotation.to_radians())
+        * Projection::translate(-ellipse.center.0, -ellipse.center.1);
+    let mut output = ImageBuffer::new(width, height);
+    imageproc::geometric_transformations::warp_into(
+        image,
+        &projection,
+        Interpolation::Bicubic,
+        Rgba([0, 0, 0, 0]),
+        &mut output,
+    );
+
+    // Approximate the distance to the ellipse edge from the implicit function and its gradient
+    let mask = ImageBuffer::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+        let (u, v) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+        let value = (u / a).powi(2) + (v / b).powi(2);
+        let gradient = 2.0 * ((u / (a * a)).powi(2) + (v / (b * b)).powi(2)).sqrt();
+        let distance = if gradient > 0.0 {
+            (value - 1.0) / gradient
+        } else {
+            -a
+        };
+        Luma([((0.5 - distance).clamp(0.0, 1.0) * 255.0).round() as u8])
+    });
+    alpha_channel::replace(&mut output, &mask);
+    output
+}
@@ -1,15 +1,78 @@
 use image::ImageBuffer;
 use image::Luma;
+use imageproc::distance_transform::Norm;
+use imageproc::rect::Rect;
 use imageproc::region_labelling::Connectivity;
 
-/// Split gray image into a list of gray images, with each blob by itself
-pub(crate) fn extract_blobs(
-    image: &ImageBuffer<Luma<u8>, Vec<u8>>,
-) -> Vec<ImageBuffer<Luma<u8>, Vec<u8>>> {
-    let (width, height) = image.dimensions();
+/// Pixels this close to the blob edge are left out of the inner mask, as the edge has shadows of its own
+const EDGE_MARGIN: u8 = 8;
+
+/// A connected component from the image mask, with the mask cropped to its bounding box
+pub(crate) struct Blob {
+    pub(crate) label: u32,
+    /// Label of the component this blob was split from, None if it wasn't split
+    pub(crate) parent: Option<u32>,
+    pub(crate) area: u32,
+    pub(crate) bounding_box: Rect,
+    pub(crate) centroid: (f32, f32),
+    pub(crate) mask: ImageBuffer<Luma<u8>, Vec<u8>>,
+}
+
+impl Blob {
+    /// Paste the cropped mask into a black grayscale image of the specified size
+    /// Only meant for debug output, processing uses the cropped mask or a region around it
+    pub(crate) fn to_full_size_mask(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let mut image = ImageBuffer::new(width, height);
+        image::imageops::replace(
+            &mut image,
+            &self.mask,
+            self.bounding_box.left() as i64,
+            self.bounding_box.top() as i64,
+        );
+        image
+    }
+
+    /// Paste the cropped mask into a black grayscale image covering the region of the scan
+    /// The region must contain the bounding box
+    pub(crate) fn to_region_mask(&self, region: &Rect) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let mut image = ImageBuffer::new(region.width(), region.height());
+        image::imageops::replace(
+            &mut image,
+            &self.mask,
+            (self.bounding_box.left() - region.left()) as i64,
+            (self.bounding_box.top() - region.top()) as i64,
+        );
+        image
+    }
+
+    /// The cropped mask without the pixels near the blob edge, for looking at what is on the item
+    pub(crate) fn inner_mask(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        imageproc::morphology::erode(&self.mask, Norm::LInf, EDGE_MARGIN)
+    }
+}
+
+/// Running totals for a single label while scanning the labelled image
+struct BlobAccumulator {
+    area: u32,
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+    sum_x: u64,
+    sum_y: u64,
+}
+
+/// Split gray image into a list of blob records, with each blob mask cropped to its bounds
+pub(crate) fn extract_blobs(image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<Blob> {
     let image_components =
         imageproc::region_labelling::connected_components(image, Connectivity::Four, Luma([0u8]));
-    let mut blobs: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = Vec::new();
+
+    // First pass: collect area, bounds and centroid sums per label
+    let mut accumulators: Vec<BlobAccumulator> = Vec::new();
     for (x, y, pixel) in image_components.enumerate_pixels() {
         let index = pixel[0] as usize;
         // Skip background color
@@ -17,15 +80,58 @@ pub(crate) fn extract_blobs(
             continue;
         }
         let index = index - 1;
-        let blob_option = blobs.get_mut(index);
-        if blob_option.is_none() {
-            let blob = ImageBuffer::new(width, height);
-            blobs.push(blob);
-        };
-        let blob_option = blobs.get_mut(index);
-        if let Some(blob) = blob_option {
-            blob.put_pixel(x, y, Luma([255u8]));
-        };
+        if index >= accumulators.len() {
+            accumulators.resize_with(index + 1, || BlobAccumulator {
+                area: 0,
+                left: u32::MAX,
+                top: u32::MAX,
+                right: 0,
+                bottom: 0,
+                sum_x: 0,
+                sum_y: 0,
+            });
+        }
+        let acc = &mut accumulators[index];
+        acc.area += 1;
+        acc.left = acc.left.min(x);
+        acc.top = acc.top.min(y);
+        acc.right = acc.right.max(x);
+        acc.bottom = acc.bottom.max(y);
+        acc.sum_x += x as u64;
+        acc.sum_y += y as u64;
+    }
+
+    // Allocate masks only as large as each blob
+    let mut blobs: Vec<Blob> = accumulators
+        .iter()
+        .enumerate()
+        .map(|(index, acc)| {
+            let width = acc.right - acc.left + 1;
+            let height = acc.bottom - acc.top + 1;
+            Blob {
+                label: index as u32 + 1,
+                parent: None,
+                area: acc.area,
+                bounding_box: Rect::at(acc.left as i32, acc.top as i32).of_size(width, height),
+                centroid: (
+                    acc.sum_x as f32 / acc.area as f32,
+                    acc.sum_y as f32 / acc.area as f32,
+                ),
+                mask: ImageBuffer::new(width, height),
+            }
+        })
+        .collect();
+
+    // Second pass: draw each label into its own cropped mask
+    for (x, y, pixel) in image_components.enumerate_pixels() {
+        let index = pixel[0] as usize;
+        if index == 0 {
+            continue;
+        }
+        let blob = &mut blobs[index - 1];
+        let left = blob.bounding_box.left() as u32;
+        let top = blob.bounding_box.top() as u32;
+        blob.mask.put_pixel(x - left, y - top, Luma([255u8]));
     }
     blobs
 }
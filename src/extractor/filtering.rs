@@ -0,0 +1,108 @@
+use std::fmt;
+
+use super::dpi::Dpi;
+use super::extraction::Blob;
+use super::geometry;
+
+/// Limits a blob must be within to be kept
+pub(crate) struct BlobFilter {
+    pub(crate) min_area: Option<u32>,
+    pub(crate) max_area: Option<u32>,
+    pub(crate) min_size: Option<f32>,
+    pub(crate) max_size: Option<f32>,
+    pub(crate) min_aspect_ratio: Option<f32>,
+    pub(crate) max_aspect_ratio: Option<f32>,
+}
+
+/// Reason a blob was dropped by the filter
+pub(crate) enum Rejection {
+    AreaBelowMinimum { area: u32, limit: u32 },
+    AreaAboveMaximum { area: u32, limit: u32 },
+    SizeBelowMinimum { size: f32, limit: f32 },
+    SizeAboveMaximum { size: f32, limit: f32 },
+    AspectRatioBelowMinimum { ratio: f32, limit: f32 },
+    AspectRatioAboveMaximum { ratio: f32, limit: f32 },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::AreaBelowMinimum { area, limit } => {
+                write!(f, "area {area} px is below minimum {limit} px")
+            }
+            Rejection::AreaAboveMaximum { area, limit } => {
+                write!(f, "area {area} px is above maximum {limit} px")
+            }
+            Rejection::SizeBelowMinimum { size, limit } => {
+                write!(f, "short side {size:.1} mm is below minimum {limit} mm")
+            }
+            Rejection::SizeAboveMaximum { size, limit } => {
+                write!(f, "long side {size:.1} mm is above maximum {limit} mm")
+            }
+            Rejection::AspectRatioBelowMinimum { ratio, limit } => {
+                write!(f, "aspect ratio {ratio:.2} is below minimum {limit}")
+            }
+            Rejection::AspectRatioAboveMaximum { ratio, limit } => {
+                write!(f, "aspect ratio {ratio:.2} is above maximum {limit}")
+            }
+        }
+    }
+}
+
+impl BlobFilter {
+    /// Check blob against all limits, returning the first one it violates
+    /// Physical size uses the sides of the minimum-area rectangle converted to millimeters, so tilted
+    /// items are measured along their own edges, aspect ratio is long side divided by short side
+    pub(crate) fn check(&self, blob: &Blob, dpi: &Dpi) -> Option<Rejection> {
+        let area = blob.area;
+        if let Some(limit) = self.min_area.filter(|&limit| area < limit) {
+            return Some(Rejection::AreaBelowMinimum { area, limit });
+        }
+        if let Some(limit) = self.max_area.filter(|&limit| area > limit) {
+            return Some(Rejection::AreaAboveMaximum { area, limit });
+        }
+        let (width, height) = side_lengths(blob, dpi);
+        let short_side = width.min(height);
+        let long_side = width.max(height);
+        if let Some(limit) = self.min_size.filter(|&limit| short_side < limit) {
+            return Some(Rejection::SizeBelowMinimum {
+                size: short_side,
+                limit,
+            });
+        }
+        if let Some(limit) = self.max_size.filter(|&limit| long_side > limit) {
+            return Some(Rejection::SizeAboveMaximum {
+                size: long_side,
+                limit,
+            });
+        }
+        let ratio = long_side / short_side;
+        if let Some(limit) = self.min_aspect_ratio.filter(|&limit| ratio < limit) {
+            return Some(Rejection::AspectRatioBelowMinimum { ratio, limit });
+        }
+        if let Some(limit) = self.max_aspect_ratio.filter(|&limit| ratio > limit) {
+            return Some(Rejection::AspectRatioAboveMaximum { ratio, limit });
+        }
+        None
+    }
+}
+
+/// Side lengths of the minimum-area rectangle around the blob in millimeters, or of the bounding
+/// box if there is no such rectangle
+fn side_lengths(blob: &Blob, dpi: &Dpi) -> (f32, f32) {
+    let Some(rect) = geometry::min_area_rect(&geometry::outline_hull(&blob.mask)) else {
+        return (
+            dpi.x_to_millimeters(blob.bounding_box.width()),
+            dpi.y_to_millimeters(blob.bounding_box.height()),
+        );
+    };
+    // Split each side into its horizontal and vertical part, as they can have a different DPI
+    let (sin, cos) = rect.angle.to_radians().sin_cos();
+    let millimeters = |length: f32, (dx, dy): (f32, f32)| {
+        (length * dx / dpi.x as f32).hypot(length * dy / dpi.y as f32) * 25.4
+    };
+    (
+        millimeters(rect.width, (cos, sin)),
+        millimeters(rect.height, (-sin, cos)),
+    )
+}
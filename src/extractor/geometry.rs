@@ -0,0 +1,233 @@
+use image::ImageBuffer;
+use image::Luma;
+use imageproc::contours::BorderType;
+use imageproc::point::Point;
+
+/// A rectangle that need not be axis-aligned
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RotatedRect {
+    pub(crate) center: (f32, f32),
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    /// Clockwise rotation of the rectangle in degrees, always within (-45, 45]
+    pub(crate) angle: f32,
+}
+
+impl RotatedRect {
+    pub(crate) fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
+    /// Corner points, ordered top-left, top-right, bottom-right, bottom-left in the rectangle's own frame
+    pub(crate) fn corners(&self) -> [(f32, f32); 4] {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (half_width, half_height) = (self.width / 2.0, self.height / 2.0);
+        [
+            (-half_width, -half_height),
+            (half_width, -half_height),
+            (half_width, half_height),
+            (-half_width, half_height),
+        ]
+        .map(|(u, v)| {
+            (
+                self.center.0 + u * cos - v * sin,
+                self.center.1 + u * sin + v * cos,
+            )
+        })
+    }
+}
+
+/// Trace the outer borders of all foreground regions in a grayscale mask, one contour per region
+/// The mask is padded first, as contour tracing doesn't pick up regions touching the image edge
+pub(crate) fn outer_contours(mask: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<Vec<Point<i32>>> {
+    let mut padded = ImageBuffer::new(mask.width() + 2, mask.height() + 2);
+    image::imageops::replace(&mut padded, mask, 1, 1);
+    imageproc::contours::find_contours::<i32>(&padded)
+        .into_iter()
+        .filter(|contour| contour.border_type == BorderType::Outer)
+        .map(|contour| {
+            contour
+                .points
+                .into_iter()
+                .map(|p| Point::new(p.x - 1, p.y - 1))
+                .collect()
+        })
+        .collect()
+}
+
+/// Find the points on the outer borders of all foreground regions in a grayscale mask
+pub(crate) fn outline_points(mask: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<Point<i32>> {
+    outer_contours(mask).into_iter().flatten().collect()
+}
+
+/// Compute convex hull of the mask outline, using pixel corners so the hull area matches the pixel area
+pub(crate) fn outline_hull(mask: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<Point<i32>> {
+    let corners: Vec<Point<i32>> = outline_points(mask)
+        .iter()
+        .flat_map(|p| {
+            [
+                Point::new(p.x, p.y),
+                Point::new(p.x + 1, p.y),
+                Point::new(p.x + 1, p.y + 1),
+                Point::new(p.x, p.y + 1),
+            ]
+        })
+        .collect();
+    imageproc::geometry::convex_hull(corners)
+}
+
+/// Area enclosed by a polygon
+pub(crate) fn polygon_area(points: &[Point<i32>]) -> f32 {
+    if points.len() < 3 {
+        return 0.0;
+    }
+    imageproc::geometry::contour_area(points) as f32
+}
+
+/// Length of the closed outline of a polygon
+pub(crate) fn polygon_perimeter(points: &[Point<i32>]) -> f32 {
+    if points.len() < 2 {
+        return 0.0;
+    }
+    imageproc::geometry::arc_length(points, true) as f32
+}
+
+/// Fold an angle in degrees into (-45, 45], treating directions 90 degrees apart as the same
+pub(crate) fn fold_angle(angle: f32) -> f32 {
+    let folded = angle.rem_euclid(90.0);
+    if folded > 45.0 { folded - 90.0 } else { folded }
+}
+
+/// Average angles in degrees that repeat every 90 degrees, using circular statistics
+/// Returns the mean angle within (-45, 45] and the resultant length (1 when all angles agree, 0 when spread evenly)
+pub(crate) fn folded_circular_mean(angles: &[f32]) -> (f32, f32) {
+    if angles.is_empty() {
+        return (0.0, 0.0);
+    }
+    // Scale by 4 so one 90 degree period maps to the full circle
+    let (sum_sin, sum_cos) = angles.iter().fold((0.0, 0.0), |(s, c), a| {
+        let (sin, cos) = (a * 4.0).to_radians().sin_cos();
+        (s + sin, c + cos)
+    });
+    let n = angles.len() as f32;
+    let mean = fold_angle(sum_sin.atan2(sum_cos).to_degrees() / 4.0);
+    let resultant_length = (sum_sin * sum_sin + sum_cos * sum_cos).sqrt() / n;
+    (mean, resultant_length)
+}
+
+/// Find the rectangle of least area enclosing a convex hull, using rotating calipers
+/// One side of that rectangle lies on a hull edge, and the hull points furthest along, across and
+/// back along each edge only move forward around the hull, so each edge takes constant time
+/// Unlike imageproc::geometry::min_area_rect the result keeps sub-pixel precision
+pub(crate) fn min_area_rect(hull: &[Point<i32>]) -> Option<RotatedRect> {
+    let count = hull.len();
+    if count < 3 {
+        return None;
+    }
+    let point = |index: usize| {
+        let p = hull[index % count];
+        (p.x as f32, p.y as f32)
+    };
+    // Calipers as indices that keep increasing, the hull point is the index modulo the count
+    let (mut forward, mut across, mut backward) = (1, 1, 1);
+    let mut best: Option<RotatedRect> = None;
+    for index in 0..count {
+        let (a, b) = (point(index), point(index + 1));
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        if dx == 0.0 && dy == 0.0 {
+            continue;
+        }
+        let along = |index: usize| {
+            let (x, y) = point(index);
+            (x - a.0) * dx + (y - a.1) * dy
+        };
+        let away = |index: usize| {
+            let (x, y) = point(index);
+            ((x - a.0) * dy - (y - a.1) * dx).abs()
+        };
+        forward = advance_caliper(forward.max(index + 1), count, along);
+        across = advance_caliper(across.max(forward), count, away);
+        backward = advance_caliper(backward.max(across), count, |index| -along(index));
+
+        // Measure in the folded frame, the calipers touch the extremes along both of its axes
+        let angle = fold_angle(dy.atan2(dx).to_degrees());
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (mut min_u, mut max_u, mut min_v, mut max_v) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+        for (x, y) in [a, point(forward), point(across), point(backward)] {
+            let u = x * cos + y * sin;
+            let v = -x * sin + y * cos;
+            min_u = min_u.min(u);
+            max_u = max_u.max(u);
+            min_v = min_v.min(v);
+            max_v = max_v.max(v);
+        }
+        let (width, height) = (max_u - min_u, max_v - min_v);
+        if best.is_some_and(|rect| rect.area() <= width * height) {
+            continue;
+        }
+        let (center_u, center_v) = ((min_u + max_u) / 2.0, (min_v + max_v) / 2.0);
+        best = Some(RotatedRect {
+            center: (
+                center_u * cos - center_v * sin,
+                center_u * sin + center_v * cos,
+            ),
+            width,
+            height,
+            angle,
+        });
+    }
+    best
+}
+
+/// Move a caliper forward around the hull while the key doesn't decrease, at most once around
+fn advance_caliper(mut index: usize, count: usize, key: impl Fn(usize) -> f32) -> usize {
+    for _ in 0..count {
+        if key(index + 1) < key(index) {
+            break;
+        }
+        index += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_angles() {
+        assert_eq!(fold_angle(10.0), 10.0);
+        assert_eq!(fold_angle(50.0), -40.0);
+        assert_eq!(fold_angle(-45.0), 45.0);
+        assert_eq!(fold_angle(90.0), 0.0);
+        assert_eq!(fold_angle(-100.0), -10.0);
+    }
+
+    #[test]
+    fn averages_folded_angles() {
+        assert_eq!(folded_circular_mean(&[]), (0.0, 0.0));
+        let (mean, length) = folded_circular_mean(&[10.0, 100.0, -80.0]);
+        assert!((mean - 10.0).abs() < 1e-3 && (length - 1.0).abs() < 1e-3);
+        // Angles on both sides of the fold average to the fold, not to 0
+        let (mean, length) = folded_circular_mean(&[44.0, -44.0]);
+        assert!((mean - 45.0).abs() < 1e-3 && length > 0.99);
+        let (_, length) = folded_circular_mean(&[0.0, 45.0]);
+        assert!(length < 1e-3);
+    }
+
+    #[test]
+    fn finds_rotated_rectangle() {
+        // 100x50 rectangle along the direction (4, 3), with points along its sides too
+        let hull = [(0, 0), (40, 30), (80, 60), (50, 100), (-30, 40), (-15, 20)]
+            .map(|(x, y)| Point::new(x, y));
+        let rect = min_area_rect(&hull).unwrap();
+        assert!((rect.width - 100.0).abs() < 1e-3 && (rect.height - 50.0).abs() < 1e-3);
+        assert!((rect.angle - 36.8699).abs() < 1e-3);
+        assert!((rect.center.0 - 25.0).abs() < 1e-3 && (rect.center.1 - 50.0).abs() < 1e-3);
+        let expected = [(0.0, 0.0), (80.0, 60.0), (50.0, 100.0), (-30.0, 40.0)];
+        for (corner, expected) in rect.corners().into_iter().zip(expected) {
+            assert!((corner.0 - expected.0).hypot(corner.1 - expected.1) < 1e-3);
+        }
+        assert!(min_area_rect(&hull[..2]).is_none());
+    }
+}
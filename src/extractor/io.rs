@@ -15,6 +15,7 @@ use little_exif::metadata::Metadata;
 
 use super::dpi::Dpi;
 use super::dpi::decoder;
+use super::metadata::BlobMetadata;
 use crate::Result;
 
 /// Open image file and decode DPI from file metadata, if any
@@ -46,6 +47,15 @@ impl ImageSaver {
             is_debugging,
         }
     }
+    /// Are we saving intermediary images?
+    pub fn is_debugging(&self) -> bool {
+        self.is_debugging
+    }
+    /// Pixel density of the saved images
+    pub fn dpi(&self) -> &Dpi {
+        &self.dpi
+    }
+
     /// Save RGBA image to PNG file with suffix appended before extension (includes pixel density header)
     pub fn save_rgba_image_as(
         &self,
@@ -74,11 +84,13 @@ impl ImageSaver {
     }
 
     /// Save RGBA image to PNG file with suffix appended before extension (includes pixel density header and text blocks)
-    pub fn save_rgba_image_with_text_as(
+    /// Each metadata entry is written as its own text block
+    pub(crate) fn save_rgba_image_with_text_as(
         &self,
         img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
         suffix: &str,
         text: &str,
+        metadata: &BlobMetadata,
     ) -> Result<()> {
         let filename = self.compute_path(suffix);
         let file = File::create(&filename)?;
@@ -95,6 +107,12 @@ impl ImageSaver {
             "Content".to_owned(),
             text.to_owned(),
         )];
+        for (key, value) in metadata.entries() {
+            info.utf8_text.push(png::text_metadata::ITXtChunk::new(
+                key.to_owned(),
+                value.to_owned(),
+            ));
+        }
         // TODO: Figure out a crate that can generate EXIF chunk with comment
         info.exif_metadata = None;
 
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use image::imageops::FilterType;
+use image::{ImageBuffer, Luma, Rgba};
+
+use super::transform::Transform;
+
+/// Shadow opacity relative to the alpha of the item casting it
+const SHADOW_OPACITY: f32 = 0.5;
+
+/// Canvas the output is centered on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Canvas {
+    /// Fixed width and height in pixels, larger items are scaled down to fit
+    Size(u32, u32),
+    /// Smallest canvas with this width to height ratio that holds the item
+    AspectRatio(f32),
+}
+
+impl FromStr for Canvas {
+    type Err = String;
+
+    /// Parse a size like 1200x800, or an aspect ratio like 3:2
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some((width, height)) = value.split_once('x') {
+            let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| n > 0);
+            match (parse(width), parse(height)) {
+                (Some(width), Some(height)) => Ok(Canvas::Size(width, height)),
+                _ => Err(format!(
+                    "Invalid canvas size '{value}', expected pixels like 1200x800"
+                )),
+            }
+        } else if let Some((width, height)) = value.split_once(':') {
+            let parse = |n: &str| n.trim().parse::<f32>().ok().filter(|&n| n > 0.0);
+            match (parse(width), parse(height)) {
+                (Some(width), Some(height)) => Ok(Canvas::AspectRatio(width / height)),
+                _ => Err(format!(
+                    "Invalid canvas aspect ratio '{value}', expected like 3:2"
+                )),
+            }
+        } else {
+            Err(format!(
+                "Invalid canvas '{value}', expected a size like 1200x800 or an aspect ratio like 3:2"
+            ))
+        }
+    }
+}
+
+/// How an extracted item is placed in the output image, all lengths in pixels
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Layout {
+    pub(crate) padding: u32,
+    pub(crate) canvas: Option<Canvas>,
+    pub(crate) background: Rgba<u8>,
+    pub(crate) shadow_offset: Option<u32>,
+}
+
+impl Layout {
+    /// Check if the layout leaves the item as it is
+    pub(crate) fn is_plain(&self) -> bool {
+        self.padding == 0
+            && self.canvas.is_none()
+            && self.shadow_offset.is_none_or(|offset| offset == 0)
+    }
+
+    /// Add padding and drop shadow around the item, and center it on the canvas
+    /// The transform is extended with where the item ends up
+    pub(crate) fn apply(
+        &self,
+        item: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        transform: &mut Transform,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let shadow_offset = self.shadow_offset.filter(|&offset| offset > 0);
+        // Shadow is blurred with half its offset, so it needs room for three times that
+        let shadow_blur = shadow_offset.map_or(0.0, |offset| offset as f32 / 2.0);
+        let shadow_reach = (3.0 * shadow_blur).ceil() as u32;
+        let margin = self.padding + shadow_offset.map_or(0, |offset| offset + shadow_reach);
+        let (width, height) = (item.width() + 2 * margin, item.height() + 2 * margin);
+        let mut composed = ImageBuffer::from_pixel(width, height, Rgba([0, 0, 0, 0]));
+        if let Some(offset) = shadow_offset {
+            let shadow = drop_shadow(item, shadow_blur, shadow_reach);
+            let position = (margin + offset - shadow_reach) as i64;
+            image::imageops::overlay(&mut composed, &shadow, position, position);
+        }
+        image::imageops::overlay(&mut composed, item, margin as i64, margin as i64);
+        *transform = transform.then(&Transform::translate(margin as f32, margin as f32));
+
+        let (canvas_width, canvas_height) = match self.canvas {
+            None => (width, height),
+            Some(Canvas::Size(canvas_width, canvas_height)) => (canvas_width, canvas_height),
+            Some(Canvas::AspectRatio(ratio)) => {
+                if width as f32 / height as f32 > ratio {
+                    (width, (width as f32 / ratio).round() as u32)
+                } else {
+                    ((height as f32 * ratio).round() as u32, height)
+                }
+            }
+        };
+        let composed = if width > canvas_width || height > canvas_height {
+            let scale =
+                (canvas_width as f32 / width as f32).min(canvas_height as f32 / height as f32);
+            let scaled_width = ((width as f32 * scale).round() as u32).clamp(1, canvas_width);
+            let scaled_height = ((height as f32 * scale).round() as u32).clamp(1, canvas_height);
+            // Resizing scales pixel edges, so pixel centers are scaled around the pixel corner
+            *transform = transform
+                .then(&Transform::translate(0.5, 0.5))
+                .then(&Transform::scale(
+                    scaled_width as f32 / width as f32,
+                    scaled_height as f32 / height as f32,
+                ))
+                .then(&Transform::translate(-0.5, -0.5));
+            image::imageops::resize(&composed, scaled_width, scaled_height, FilterType::Lanczos3)
+        } else {
+            composed
+        };
+        let mut canvas = ImageBuffer::from_pixel(canvas_width, canvas_height, self.background);
+        let left = (canvas_width - composed.width()) / 2;
+        let top = (canvas_height - composed.height()) / 2;
+        image::imageops::overlay(&mut canvas, &composed, left as i64, top as i64);
+        *transform = transform.then(&Transform::translate(left as f32, top as f32));
+        canvas
+    }
+}
+
+/// Black shadow shaped like the alpha channel of the item, blurred and with room for the blur
+fn drop_shadow(
+    item: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    blur: f32,
+    reach: u32,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut alpha = ImageBuffer::new(item.width() + 2 * reach, item.height() + 2 * reach);
+    for (x, y, pixel) in item.enumerate_pixels() {
+        let value = (pixel[3] as f32 * SHADOW_OPACITY).round() as u8;
+        alpha.put_pixel(x + reach, y + reach, Luma([value]));
+    }
+    let alpha = imageproc::filter::gaussian_blur_f32(&alpha, blur);
+    ImageBuffer::from_fn(alpha.width(), alpha.height(), |x, y| {
+        Rgba([0, 0, 0, alpha.get_pixel(x, y)[0]])
+    })
+}
@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+use super::dpi::Dpi;
+
+/// A length in pixels, or in millimeters converted to pixels at the DPI of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Length {
+    Pixels(f32),
+    Millimeters(f32),
+}
+
+impl Length {
+    /// Length in pixels at the given pixel density
+    pub(crate) fn to_pixels(self, dpi: &Dpi) -> f32 {
+        match self {
+            Length::Pixels(pixels) => pixels,
+            Length::Millimeters(millimeters) => dpi.millimeters_to_pixels(millimeters),
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    /// Parse a number with an optional unit, like 10, 10px or 0.8mm
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (number, unit): (&str, fn(f32) -> Length) =
+            if let Some(number) = value.strip_suffix("mm") {
+                (number, Length::Millimeters)
+            } else if let Some(number) = value.strip_suffix("px") {
+                (number, Length::Pixels)
+            } else {
+                (value, Length::Pixels)
+            };
+        let number = number.trim().parse::<f32>().map_err(|_| {
+            format!("Invalid length '{value}', expected pixels or millimeters like 10 or 0.8mm")
+        })?;
+        if !number.is_finite() || number < 0.0 {
+            return Err("Length must not be negative".to_string());
+        }
+        Ok(unit(number))
+    }
+}
+
+/// Parameters that can be given in physical units, kept until the DPI of the image is known
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Lengths {
+    pub(crate) trim_edges: Length,
+    pub(crate) grow_edges: Length,
+    pub(crate) blur_edge_factor: Length,
+    pub(crate) min_pixels_touching_line: Length,
+    pub(crate) line_suppression_radius: Length,
+    pub(crate) feather: Length,
+    pub(crate) padding: Length,
+    pub(crate) drop_shadow: Option<Length>,
+    pub(crate) contour_tolerance: Length,
+    pub(crate) overhang_margin: Option<Length>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pixels_and_millimeters() {
+        assert_eq!("10".parse::<Length>(), Ok(Length::Pixels(10.0)));
+        assert_eq!("10px".parse::<Length>(), Ok(Length::Pixels(10.0)));
+        assert_eq!("0.8mm".parse::<Length>(), Ok(Length::Millimeters(0.8)));
+        assert_eq!(" 2 mm ".parse::<Length>(), Ok(Length::Millimeters(2.0)));
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        for value in ["", "abc", "1cm", "-1", "-0.5mm", "inf", "NaN"] {
+            assert!(value.parse::<Length>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn converts_millimeters_at_dpi() {
+        let dpi = Dpi::new(254u32);
+        assert!((Length::Millimeters(1.0).to_pixels(&dpi) - 10.0).abs() < 1e-4);
+        assert_eq!(Length::Pixels(7.0).to_pixels(&dpi), 7.0);
+    }
+}
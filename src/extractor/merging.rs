@@ -0,0 +1,104 @@
+use image::ImageBuffer;
+use image::Luma;
+use imageproc::rect::Rect;
+
+use super::extraction::Blob;
+use super::geometry;
+
+/// Check if the bounding boxes of two blobs overlap or are within gap pixels of each other
+pub(crate) fn are_close(a: &Blob, b: &Blob, gap: u32) -> bool {
+    let gap = gap as i32;
+    let (a, b) = (a.bounding_box, b.bounding_box);
+    a.left() - gap <= b.right()
+        && b.left() - gap <= a.right()
+        && a.top() - gap <= b.bottom()
+        && b.top() - gap <= a.bottom()
+}
+
+/// Combine two blobs into one, keeping the label of the first
+pub(crate) fn merge(a: &Blob, b: &Blob) -> Blob {
+    let left = a.bounding_box.left().min(b.bounding_box.left());
+    let top = a.bounding_box.top().min(b.bounding_box.top());
+    let right = a.bounding_box.right().max(b.bounding_box.right());
+    let bottom = a.bounding_box.bottom().max(b.bounding_box.bottom());
+    let width = (right - left + 1) as u32;
+    let height = (bottom - top + 1) as u32;
+    let mut mask = ImageBuffer::new(width, height);
+    for blob in [a, b] {
+        let offset_x = (blob.bounding_box.left() - left) as u32;
+        let offset_y = (blob.bounding_box.top() - top) as u32;
+        for (x, y, pixel) in blob.mask.enumerate_pixels() {
+            if pixel[0] > 0 {
+                mask.put_pixel(x + offset_x, y + offset_y, Luma([255u8]));
+            }
+        }
+    }
+    let area = a.area + b.area;
+    Blob {
+        label: a.label,
+        parent: a.parent,
+        area,
+        bounding_box: Rect::at(left, top).of_size(width, height),
+        centroid: (
+            (a.centroid.0 * a.area as f32 + b.centroid.0 * b.area as f32) / area as f32,
+            (a.centroid.1 * a.area as f32 + b.centroid.1 * b.area as f32) / area as f32,
+        ),
+        mask,
+    }
+}
+
+/// How well the blob fills its minimum-area enclosing rectangle (0-1)
+pub(crate) fn rectangle_fill(blob: &Blob) -> f32 {
+    let hull = geometry::outline_hull(&blob.mask);
+    match geometry::min_area_rect(&hull) {
+        Some(rect) if rect.area() > 0.0 => blob.area as f32 / rect.area(),
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::extraction;
+    use super::*;
+
+    /// Two fragments of a 100x100 item, split by a vertical tear of the given width
+    fn fragments(tear: u32) -> (Blob, Blob) {
+        let mask = ImageBuffer::from_fn(120, 120, |x, y| {
+            let inside = (10..110).contains(&x) && (10..110).contains(&y);
+            let torn = (60..60 + tear).contains(&x);
+            Luma([if inside && !torn { 255u8 } else { 0 }])
+        });
+        let mut blobs = extraction::extract_blobs(&mask).into_iter();
+        let (a, b) = (blobs.next().unwrap(), blobs.next().unwrap());
+        assert!(blobs.next().is_none());
+        (a, b)
+    }
+
+    #[test]
+    fn merges_fragments_within_gap() {
+        let (a, b) = fragments(5);
+        // The tear is 5 pixels wide, so the boxes are 6 pixels apart
+        assert!(are_close(&a, &b, 6));
+        assert!(!are_close(&a, &b, 5));
+        let merged = merge(&a, &b);
+        assert_eq!(merged.label, a.label);
+        assert_eq!(merged.area, 95 * 100);
+        assert_eq!(merged.bounding_box, Rect::at(10, 10).of_size(100, 100));
+        assert!(
+            (merged.centroid.0 - 59.37).abs() < 0.01 && (merged.centroid.1 - 59.5).abs() < 0.01
+        );
+        assert!((rectangle_fill(&merged) - 0.95).abs() < 0.01);
+    }
+
+    #[test]
+    fn rectangle_fill_of_unrelated_items_is_low() {
+        let mask = ImageBuffer::from_fn(200, 200, |x, y| {
+            let first = x < 100 && y < 100;
+            let second = (150..200).contains(&x) && (150..200).contains(&y);
+            Luma([if first || second { 255u8 } else { 0 }])
+        });
+        let blobs = extraction::extract_blobs(&mask);
+        let merged = merge(&blobs[0], &blobs[1]);
+        assert!(rectangle_fill(&merged) < 0.5);
+    }
+}
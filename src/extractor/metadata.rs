@@ -0,0 +1,17 @@
+/// Extra information about a blob, saved as text chunks in the output image
+#[derive(Debug, Default)]
+pub(crate) struct BlobMetadata {
+    entries: Vec<(String, String)>,
+}
+
+impl BlobMetadata {
+    /// Add an entry, keys should be short and descriptive (PNG keywords are limited to 79 characters)
+    pub(crate) fn add(&mut self, key: &str, value: impl ToString) {
+        self.entries.push((key.to_owned(), value.to_string()));
+    }
+
+    /// All entries in the order they were added
+    pub(crate) fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+}
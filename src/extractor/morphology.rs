@@ -0,0 +1,248 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::ImageBuffer;
+use image::Luma;
+use imageproc::contrast::ThresholdType;
+use imageproc::distance_transform::Norm;
+
+/// Shape of the structuring element used by a morphology operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Kernel {
+    /// Manhattan distance (L1 norm)
+    Diamond,
+    /// Euclidean distance (L2 norm)
+    Disk,
+    /// Chessboard distance (L-infinity norm)
+    Square,
+}
+
+impl Kernel {
+    /// The imageproc norm matching this kernel shape
+    fn norm(&self) -> Norm {
+        match self {
+            Kernel::Diamond => Norm::L1,
+            Kernel::Disk => Norm::L2,
+            Kernel::Square => Norm::LInf,
+        }
+    }
+}
+
+impl FromStr for Kernel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "diamond" | "l1" => Ok(Kernel::Diamond),
+            "disk" | "l2" => Ok(Kernel::Disk),
+            "square" | "linf" => Ok(Kernel::Square),
+            _ => Err(format!(
+                "Unknown kernel '{value}', expected diamond (l1), disk (l2) or square (linf)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kernel::Diamond => write!(f, "diamond"),
+            Kernel::Disk => write!(f, "disk"),
+            Kernel::Square => write!(f, "square"),
+        }
+    }
+}
+
+/// A single step in a morphology program
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operation {
+    Erode(Kernel, u8),
+    Dilate(Kernel, u8),
+    Open(Kernel, u8),
+    Close(Kernel, u8),
+    Blur(f32),
+    Threshold(u8),
+}
+
+impl Operation {
+    /// Apply operation to grayscale image
+    fn apply(&self, image: &mut ImageBuffer<Luma<u8>, Vec<u8>>) {
+        match self {
+            Operation::Erode(kernel, k) => {
+                imageproc::morphology::erode_mut(image, kernel.norm(), *k)
+            }
+            Operation::Dilate(kernel, k) => {
+                imageproc::morphology::dilate_mut(image, kernel.norm(), *k)
+            }
+            Operation::Open(kernel, k) => imageproc::morphology::open_mut(image, kernel.norm(), *k),
+            Operation::Close(kernel, k) => {
+                imageproc::morphology::close_mut(image, kernel.norm(), *k)
+            }
+            Operation::Blur(sigma) => *image = imageproc::filter::gaussian_blur_f32(image, *sigma),
+            Operation::Threshold(level) => {
+                imageproc::contrast::threshold_mut(image, *level, ThresholdType::Binary)
+            }
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    /// Parse operation with format like this name:kernel:size, blur:sigma or threshold:level
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.trim().split(':').collect();
+        match parts.as_slice() {
+            [name, kernel, size] => {
+                let kernel = kernel.parse::<Kernel>()?;
+                let size = size
+                    .parse::<u8>()
+                    .map_err(|_| format!("Invalid size '{size}' in '{value}'"))?;
+                match *name {
+                    "erode" => Ok(Operation::Erode(kernel, size)),
+                    "dilate" => Ok(Operation::Dilate(kernel, size)),
+                    "open" => Ok(Operation::Open(kernel, size)),
+                    "close" => Ok(Operation::Close(kernel, size)),
+                    _ => Err(format!("Unknown operation '{name}' in '{value}'")),
+                }
+            }
+            ["blur", sigma] => {
+                let sigma = sigma
+                    .parse::<f32>()
+                    .map_err(|_| format!("Invalid blur sigma '{sigma}' in '{value}'"))?;
+                if sigma <= 0.0 {
+                    return Err(format!("Blur sigma must be greater than 0 in '{value}'"));
+                }
+                Ok(Operation::Blur(sigma))
+            }
+            ["threshold", level] => Ok(Operation::Threshold(parse_threshold_level(level)?)),
+            _ => Err(format!("Invalid morphology operation '{value}'")),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Erode(kernel, k) => write!(f, "erode:{kernel}:{k}"),
+            Operation::Dilate(kernel, k) => write!(f, "dilate:{kernel}:{k}"),
+            Operation::Open(kernel, k) => write!(f, "open:{kernel}:{k}"),
+            Operation::Close(kernel, k) => write!(f, "close:{kernel}:{k}"),
+            Operation::Blur(sigma) => write!(f, "blur:{sigma}"),
+            Operation::Threshold(level) => write!(f, "threshold:{level}"),
+        }
+    }
+}
+
+/// Parse threshold level, either as an absolute value (0-255) or as a percentage (like 80%)
+fn parse_threshold_level(level: &str) -> Result<u8, String> {
+    match level.strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid threshold percentage '{level}'"))?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!(
+                    "Threshold percentage '{level}' must be between 0% and 100%"
+                ));
+            }
+            Ok((percent / 100.0 * 255.0).round() as u8)
+        }
+        None => level.parse::<u8>().map_err(|_| {
+            format!("Invalid threshold level '{level}', expected 0-255 or a percentage")
+        }),
+    }
+}
+
+/// A list of morphology operations applied in order to the image mask
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Program(Vec<Operation>);
+
+impl Program {
+    /// The default cleanup: a single erode followed by a single dilate, both with a diamond kernel
+    pub(crate) fn from_trim_and_grow(trim_edges: u8, grow_edges: u8) -> Self {
+        Program(vec![
+            Operation::Erode(Kernel::Diamond, trim_edges),
+            Operation::Dilate(Kernel::Diamond, grow_edges),
+        ])
+    }
+
+    /// Apply all operations in order to grayscale image
+    pub(crate) fn apply(&self, image: &mut ImageBuffer<Luma<u8>, Vec<u8>>) {
+        for operation in &self.0 {
+            operation.apply(image);
+        }
+    }
+}
+
+impl FromStr for Program {
+    type Err = String;
+
+    /// Parse comma-separated list of operations, like erode:disk:1,blur:3,dilate:disk:3
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let operations = value
+            .split(',')
+            .filter(|op| !op.trim().is_empty())
+            .map(|op| op.parse::<Operation>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if operations.is_empty() {
+            return Err("Morphology program must contain at least one operation".to_string());
+        }
+        Ok(Program(operations))
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, operation) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{operation}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_program() {
+        let program = "erode:disk:1, blur:3,dilate:l1:3,threshold:80%,"
+            .parse::<Program>()
+            .unwrap();
+        assert_eq!(
+            program,
+            Program(vec![
+                Operation::Erode(Kernel::Disk, 1),
+                Operation::Blur(3.0),
+                Operation::Dilate(Kernel::Diamond, 3),
+                Operation::Threshold(204),
+            ])
+        );
+        assert_eq!(
+            program.to_string(),
+            "erode:disk:1,blur:3,dilate:diamond:3,threshold:204"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_programs() {
+        for value in [
+            "",
+            ",",
+            "erode:hexagon:1",
+            "erode:disk:300",
+            "shrink:disk:1",
+            "blur:0",
+            "blur:x",
+            "threshold:120%",
+            "threshold:256",
+            "erode",
+        ] {
+            assert!(value.parse::<Program>().is_err(), "{value}");
+        }
+    }
+}
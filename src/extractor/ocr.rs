@@ -1,8 +1,30 @@
+use std::ffi::CString;
+use std::os::raw::c_int;
+use std::ptr;
 use std::{fs, path::Path};
 
+use image::{ImageBuffer, Rgba};
+
 use crate::Result;
+use leptess::capi::{
+    TessBaseAPI, TessBaseAPICreate, TessBaseAPIDelete, TessBaseAPIDetectOrientationScript,
+    TessBaseAPIInit3, TessBaseAPISetImage, TessBaseAPISetPageSegMode,
+    TessBaseAPISetSourceResolution, TessPageSegMode_PSM_OSD_ONLY,
+};
+use leptess::tesseract::TessInitError;
 use leptess::{LepTess, Variable};
 
+/// Orientation confidence reported by Tesseract needed before a blob is turned, below it the
+/// orientation is mostly a guess
+const MIN_ORIENTATION_CONFIDENCE: f32 = 2.0;
+
+/// Clockwise rotation that makes the text on a blob upright, and Tesseract's confidence in it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Orientation {
+    pub(crate) rotation: u32,
+    pub(crate) confidence: f32,
+}
+
 pub(crate) struct TextExtractor {
     lt: LepTess,
 }
@@ -45,3 +67,80 @@ impl TextExtractor {
         Ok(text)
     }
 }
+
+/// Tesseract set up for orientation and script detection only (psm 0), which needs osd.traineddata
+/// It looks at the shapes of the characters in a single pass, without recognizing the text
+/// leptess doesn't wrap it, so it goes through the Tesseract C API
+pub(crate) struct OrientationDetector {
+    api: *mut TessBaseAPI,
+}
+
+impl OrientationDetector {
+    pub(crate) fn new(tessdata: &Path) -> Result<Self> {
+        let tessdata = CString::new(tessdata.to_string_lossy().as_bytes())
+            .map_err(|_| TessInitError { code: -1 })?;
+        // The detector owns the handle from here on, so it is deleted even if init fails
+        let detector = Self {
+            api: unsafe { TessBaseAPICreate() },
+        };
+        let code = unsafe { TessBaseAPIInit3(detector.api, tessdata.as_ptr(), c"osd".as_ptr()) };
+        if code != 0 {
+            return Err(TessInitError { code }.into());
+        }
+        unsafe { TessBaseAPISetPageSegMode(detector.api, TessPageSegMode_PSM_OSD_ONLY) };
+        Ok(detector)
+    }
+
+    /// Find which multiple of 90 degrees makes the text on an image upright
+    /// The resolution (DPI) lets Tesseract tell text from other shapes by size
+    /// Returns None if there is too little text, or its orientation isn't clear
+    pub(crate) fn detect(
+        &mut self,
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        resolution: u32,
+    ) -> Option<Orientation> {
+        let gray = image::imageops::grayscale(image);
+        let (width, height) = (gray.width() as c_int, gray.height() as c_int);
+        let (mut degrees, mut confidence) = (0, 0.0);
+        let (mut script, mut script_confidence) = (ptr::null(), 0.0);
+        // Tesseract copies the image, so the buffer only has to live through the call
+        let found = unsafe {
+            TessBaseAPISetImage(self.api, gray.as_ptr(), width, height, 1, width);
+            TessBaseAPISetSourceResolution(self.api, resolution as c_int);
+            TessBaseAPIDetectOrientationScript(
+                self.api,
+                &mut degrees,
+                &mut confidence,
+                &mut script,
+                &mut script_confidence,
+            )
+        };
+        if found == 0 || confidence < MIN_ORIENTATION_CONFIDENCE {
+            return None;
+        }
+        // Tesseract reports how far the text is turned clockwise, the rest of the circle turns it back
+        Some(Orientation {
+            rotation: ((360 - degrees.rem_euclid(360)) % 360) as u32,
+            confidence,
+        })
+    }
+}
+
+impl Drop for OrientationDetector {
+    fn drop(&mut self) {
+        unsafe { TessBaseAPIDelete(self.api) };
+    }
+}
+
+/// Rotate an image clockwise by a multiple of 90 degrees
+pub(crate) fn rotate_clockwise(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    rotation: u32,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    match rotation % 360 {
+        90 => image::imageops::rotate90(image),
+        180 => image::imageops::rotate180(image),
+        270 => image::imageops::rotate270(image),
+        _ => image.clone(),
+    }
+}
@@ -0,0 +1,166 @@
+use clap::ValueEnum;
+
+use super::extraction::Blob;
+
+/// How blobs are numbered in the output
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum BlobOrder {
+    /// Order in which connected component labelling found them (raster scan)
+    Label,
+    /// Rows top-to-bottom, then left-to-right within each row
+    Rows,
+    /// Columns left-to-right, then top-to-bottom within each column
+    Columns,
+    /// Largest area first
+    LargestFirst,
+    /// Closest to the chosen corner first
+    Corner,
+}
+
+/// Corner of the scan used by the corner order
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Sort blobs in the requested order
+/// Blobs whose centers are within tolerance pixels of a row (or column) are grouped together,
+/// which defaults to half the median blob height (or width)
+pub(crate) fn sort_blobs(
+    blobs: &mut [Blob],
+    order: BlobOrder,
+    tolerance: Option<u32>,
+    corner: Corner,
+    width: u32,
+    height: u32,
+) {
+    match order {
+        // Parts of a split blob take the place of the component they were split from
+        BlobOrder::Label => {
+            blobs.sort_by_key(|blob| (blob.parent.unwrap_or(blob.label), blob.label))
+        }
+        BlobOrder::Rows => sort_in_lines(blobs, tolerance, |blob| {
+            (blob.centroid.1, blob.centroid.0, blob.bounding_box.height())
+        }),
+        BlobOrder::Columns => sort_in_lines(blobs, tolerance, |blob| {
+            (blob.centroid.0, blob.centroid.1, blob.bounding_box.width())
+        }),
+        BlobOrder::LargestFirst => blobs.sort_by_key(|blob| std::cmp::Reverse(blob.area)),
+        BlobOrder::Corner => {
+            let (cx, cy) = match corner {
+                Corner::TopLeft => (0.0, 0.0),
+                Corner::TopRight => (width as f32, 0.0),
+                Corner::BottomLeft => (0.0, height as f32),
+                Corner::BottomRight => (width as f32, height as f32),
+            };
+            let distance = |blob: &Blob| (blob.centroid.0 - cx).hypot(blob.centroid.1 - cy);
+            blobs.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        }
+    }
+}
+
+/// Group blobs into lines along the primary axis, then order each line along the secondary axis
+/// The key function returns primary position, secondary position and extent along the primary axis
+fn sort_in_lines<F>(blobs: &mut [Blob], tolerance: Option<u32>, key: F)
+where
+    F: Fn(&Blob) -> (f32, f32, u32),
+{
+    if blobs.is_empty() {
+        return;
+    }
+    let tolerance = match tolerance {
+        Some(tolerance) => tolerance as f32,
+        None => {
+            let mut extents: Vec<u32> = blobs.iter().map(|blob| key(blob).2).collect();
+            extents.sort();
+            extents[extents.len() / 2] as f32 / 2.0
+        }
+    };
+    blobs.sort_by(|a, b| key(a).0.total_cmp(&key(b).0));
+
+    // Split into lines where a blob is too far from the running line mean, then order each line
+    let mut start = 0;
+    let mut line_sum = 0.0;
+    for index in 0..blobs.len() {
+        let primary = key(&blobs[index]).0;
+        if index > start && primary - line_sum / (index - start) as f32 > tolerance {
+            blobs[start..index].sort_by(|a, b| key(a).1.total_cmp(&key(b).1));
+            start = index;
+            line_sum = 0.0;
+        }
+        line_sum += primary;
+    }
+    blobs[start..].sort_by(|a, b| key(a).1.total_cmp(&key(b).1));
+}
+
+#[cfg(test)]
+mod tests {
+    use image::ImageBuffer;
+    use imageproc::rect::Rect;
+
+    use super::*;
+
+    /// 80x80 blob centered on the given point
+    fn blob(label: u32, (x, y): (f32, f32)) -> Blob {
+        Blob {
+            label,
+            parent: None,
+            area: 6400,
+            bounding_box: Rect::at(x as i32 - 40, y as i32 - 40).of_size(80, 80),
+            centroid: (x, y),
+            mask: ImageBuffer::new(80, 80),
+        }
+    }
+
+    /// Labels of the blobs in the given order, from items given in scan order
+    fn order(centers: &[(f32, f32)], order: BlobOrder, tolerance: Option<u32>) -> Vec<u32> {
+        // Labelled in reverse, so the result doesn't come from the labelling
+        let mut blobs: Vec<Blob> = centers
+            .iter()
+            .enumerate()
+            .map(|(index, &center)| blob(index as u32 + 1, center))
+            .rev()
+            .collect();
+        sort_blobs(&mut blobs, order, tolerance, Corner::TopLeft, 1000, 1000);
+        blobs.iter().map(|blob| blob.label).collect()
+    }
+
+    #[test]
+    fn rows_stay_stable_with_jitter() {
+        let grid = [
+            (100.0, 100.0),
+            (300.0, 100.0),
+            (500.0, 100.0),
+            (100.0, 300.0),
+            (300.0, 300.0),
+            (500.0, 300.0),
+        ];
+        // Items placed a little higher or lower on a rescan, some above the one before them
+        let jittered = [
+            (104.0, 118.0),
+            (297.0, 85.0),
+            (502.0, 106.0),
+            (95.0, 315.0),
+            (303.0, 282.0),
+            (498.0, 296.0),
+        ];
+        let expected = vec![1, 2, 3, 4, 5, 6];
+        assert_eq!(order(&grid, BlobOrder::Rows, Some(40)), expected);
+        assert_eq!(order(&jittered, BlobOrder::Rows, Some(40)), expected);
+        // The default tolerance is half the median blob height
+        assert_eq!(order(&jittered, BlobOrder::Rows, None), expected);
+        // Columns are the same with the axes swapped
+        let swapped: Vec<(f32, f32)> = jittered.iter().map(|&(x, y)| (y, x)).collect();
+        assert_eq!(order(&swapped, BlobOrder::Columns, Some(40)), expected);
+    }
+
+    #[test]
+    fn rows_split_beyond_tolerance() {
+        let centers = [(300.0, 100.0), (100.0, 160.0)];
+        assert_eq!(order(&centers, BlobOrder::Rows, Some(40)), vec![1, 2]);
+        assert_eq!(order(&centers, BlobOrder::Rows, Some(80)), vec![2, 1]);
+    }
+}
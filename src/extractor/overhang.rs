@@ -0,0 +1,62 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use itertools::Itertools; // for join() iterator function
+
+use super::extraction::Blob;
+
+/// What to do with blobs that extend past the edge of the scan
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum OverhangPolicy {
+    /// Extract them, but flag them in logs and metadata
+    Flag,
+    /// Do not extract them
+    Skip,
+    /// Extract and flag them, and add them to a list of partial items
+    List,
+}
+
+/// Edge of the scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Edge {
+    Left,
+    Top,
+    Right,
+    Bottom,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edge::Left => write!(f, "left"),
+            Edge::Top => write!(f, "top"),
+            Edge::Right => write!(f, "right"),
+            Edge::Bottom => write!(f, "bottom"),
+        }
+    }
+}
+
+/// Find which scan edges the blob mask comes within margin pixels of
+pub(crate) fn touched_edges(blob: &Blob, width: u32, height: u32, margin: u32) -> Vec<Edge> {
+    let margin = margin as i32;
+    let bounding_box = blob.bounding_box;
+    let mut edges = Vec::new();
+    if bounding_box.left() <= margin {
+        edges.push(Edge::Left);
+    }
+    if bounding_box.top() <= margin {
+        edges.push(Edge::Top);
+    }
+    if bounding_box.right() >= width as i32 - 1 - margin {
+        edges.push(Edge::Right);
+    }
+    if bounding_box.bottom() >= height as i32 - 1 - margin {
+        edges.push(Edge::Bottom);
+    }
+    edges
+}
+
+/// Format list of edges like this: left, top
+pub(crate) fn format_edges(edges: &[Edge]) -> String {
+    edges.iter().join(", ")
+}
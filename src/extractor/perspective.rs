@@ -0,0 +1,130 @@
+use std::fmt;
+
+use imageproc::geometric_transformations::Projection;
+use itertools::Itertools; // for join() iterator function
+
+use super::extraction::Blob;
+use super::geometry;
+use super::transform::Transform;
+
+/// The quadrilateral must cover at least this fraction of the blob's convex hull
+const MIN_HULL_COVERAGE: f32 = 0.9;
+
+/// Four-sided outline in scan coordinates, with the corners on the outer edges of the pixels
+/// Corners are ordered top-left, top-right, bottom-right, bottom-left
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quad {
+    pub(crate) corners: [(f32, f32); 4],
+}
+
+impl Quad {
+    /// Area enclosed by the corners
+    pub(crate) fn area(&self) -> f32 {
+        let sum: f32 = (0..4)
+            .map(|index| {
+                let (x1, y1) = self.corners[index];
+                let (x2, y2) = self.corners[(index + 1) % 4];
+                x1 * y2 - x2 * y1
+            })
+            .sum();
+        sum.abs() / 2.0
+    }
+
+    /// Size of the rectangle the quadrilateral is warped to, from the mean length of opposite sides
+    pub(crate) fn rectified_size(&self) -> (u32, u32) {
+        let [top_left, top_right, bottom_right, bottom_left] = self.corners;
+        let width = (distance(top_left, top_right) + distance(bottom_left, bottom_right)) / 2.0;
+        let height = (distance(top_left, bottom_left) + distance(top_right, bottom_right)) / 2.0;
+        (
+            width.round().max(1.0) as u32,
+            height.round().max(1.0) as u32,
+        )
+    }
+
+    /// Projective transform from the quadrilateral to its rectified size, with origin in the top-left corner
+    /// The corners go to the outer edges of the corner pixels, like the rotated crop
+    pub(crate) fn projection(&self) -> Option<Projection> {
+        let (width, height) = self.rectified_size();
+        let (right, bottom) = (width as f32 - 0.5, height as f32 - 0.5);
+        Projection::from_control_points(
+            self.corners,
+            [(-0.5, -0.5), (right, -0.5), (right, bottom), (-0.5, bottom)],
+        )
+    }
+
+    /// Transform of points from the scan to the rectified image, the same mapping as the projection
+    pub(crate) fn transform(&self) -> Option<Transform> {
+        let (width, height) = self.rectified_size();
+        Transform::from_quad_to_rect(self.corners, width, height)
+    }
+}
+
+impl fmt::Display for Quad {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let corners = self
+            .corners
+            .iter()
+            .map(|(x, y)| format!("{x:.0}x{y:.0}"))
+            .join(" ");
+        write!(f, "{corners}")
+    }
+}
+
+/// Fit a quadrilateral to the blob outline
+/// The corners are the hull points furthest out along the diagonals of the deskewed frame
+/// Returns None if the blob isn't four-sided, like round items where the corners cut off too much
+pub(crate) fn fit_quad(blob: &Blob, deskew_angle: f32) -> Option<Quad> {
+    let hull = geometry::outline_hull(&blob.mask);
+    let hull_area = geometry::polygon_area(&hull);
+    if hull.len() < 4 || hull_area <= 0.0 {
+        return None;
+    }
+    // Project onto axes rotated like the blob, so its sides are roughly horizontal and vertical
+    let (sin, cos) = (-deskew_angle).to_radians().sin_cos();
+    let points: Vec<(f32, f32, f32, f32)> = hull
+        .iter()
+        .map(|p| {
+            let (x, y) = hull_to_scan(blob, (p.x as f32, p.y as f32));
+            (x, y, x * cos + y * sin, -x * sin + y * cos)
+        })
+        .collect();
+    let corner = |key: fn(f32, f32) -> f32| {
+        points
+            .iter()
+            .max_by(|a, b| key(a.2, a.3).total_cmp(&key(b.2, b.3)))
+            .map(|&(x, y, _, _)| (x, y))
+    };
+    let quad = Quad {
+        corners: [
+            corner(|u, v| -u - v)?,
+            corner(|u, v| u - v)?,
+            corner(|u, v| u + v)?,
+            corner(|u, v| v - u)?,
+        ],
+    };
+    if quad.area() < hull_area * MIN_HULL_COVERAGE {
+        return None;
+    }
+    Some(quad)
+}
+
+/// Fit the minimum-area rectangle to the blob outline, in scan coordinates
+pub(crate) fn fit_rectangle(blob: &Blob) -> Option<Quad> {
+    let rect = geometry::min_area_rect(&geometry::outline_hull(&blob.mask))?;
+    Some(Quad {
+        corners: rect.corners().map(|p| hull_to_scan(blob, p)),
+    })
+}
+
+/// Scan coordinates of a point on the outline hull of the blob mask
+/// The hull is traced on pixel corners, while pixel centers are at whole coordinates
+fn hull_to_scan(blob: &Blob, (x, y): (f32, f32)) -> (f32, f32) {
+    (
+        x + blob.bounding_box.left() as f32 - 0.5,
+        y + blob.bounding_box.top() as f32 - 0.5,
+    )
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use image::{ImageBuffer, Rgba};
+
+use super::dpi::Dpi;
+
+/// Standard photo print formats, short side by long side in centimeters
+const PRINT_SIZES: [(f32, f32); 13] = [
+    (6.0, 9.0),
+    (7.0, 10.0),
+    (9.0, 9.0),
+    (9.0, 13.0),
+    (10.0, 10.0),
+    (10.0, 15.0),
+    (13.0, 13.0),
+    (13.0, 18.0),
+    (15.0, 20.0),
+    (18.0, 24.0),
+    (20.0, 25.0),
+    (20.0, 30.0),
+    (24.0, 30.0),
+];
+
+/// Physical size of an image in millimeters
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PhysicalSize {
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+}
+
+impl PhysicalSize {
+    /// Size of the image at the given pixel density
+    pub(crate) fn of(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, dpi: &Dpi) -> Self {
+        Self {
+            width: dpi.x_to_millimeters(image.width()),
+            height: dpi.y_to_millimeters(image.height()),
+        }
+    }
+
+    /// Find the standard print format closest to this size, if both sides are within tolerance millimeters
+    pub(crate) fn nearest_print_size(&self, tolerance: f32) -> Option<PrintSize> {
+        let (short_side, long_side) = (self.width.min(self.height), self.width.max(self.height));
+        PRINT_SIZES
+            .iter()
+            .map(|&(short, long)| PrintSize {
+                short_side: short * 10.0,
+                long_side: long * 10.0,
+            })
+            .map(|size| {
+                let deviation = (size.short_side - short_side)
+                    .abs()
+                    .max((size.long_side - long_side).abs());
+                (size, deviation)
+            })
+            .filter(|&(_, deviation)| deviation <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(size, _)| size)
+    }
+}
+
+impl fmt::Display for PhysicalSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 1 inch = 25.4 mm
+        write!(
+            f,
+            "{:.1}x{:.1} mm ({:.2}x{:.2} in)",
+            self.width,
+            self.height,
+            self.width / 25.4,
+            self.height / 25.4
+        )
+    }
+}
+
+/// Standard print format in millimeters
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PrintSize {
+    pub(crate) short_side: f32,
+    pub(crate) long_side: f32,
+}
+
+impl fmt::Display for PrintSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{} cm", self.short_side / 10.0, self.long_side / 10.0)
+    }
+}
+
+/// Crop the image around its center to the aspect ratio of the print format, keeping its orientation
+pub(crate) fn crop_to_aspect_ratio(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    size: &PrintSize,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let ratio = size.long_side / size.short_side;
+    let (long, short) = (width.max(height) as f32, width.min(height) as f32);
+    let (long, short) = if long / short > ratio {
+        ((short * ratio).round() as u32, short as u32)
+    } else {
+        (long as u32, (long / ratio).round() as u32)
+    };
+    let (new_width, new_height) = if width >= height {
+        (long, short)
+    } else {
+        (short, long)
+    };
+    let (new_width, new_height) = (new_width.clamp(1, width), new_height.clamp(1, height));
+    image::imageops::crop_imm(
+        image,
+        (width - new_width) / 2,
+        (height - new_height) / 2,
+        new_width,
+        new_height,
+    )
+    .to_image()
+}
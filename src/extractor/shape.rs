@@ -0,0 +1,73 @@
+use std::f32::consts::PI;
+use std::fmt;
+
+use super::extraction::Blob;
+use super::geometry;
+
+/// Rough shape of a blob, used to pick deskew and crop strategy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BlobShape {
+    Rectangle,
+    Ellipse,
+    Irregular,
+}
+
+impl fmt::Display for BlobShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlobShape::Rectangle => write!(f, "rectangle"),
+            BlobShape::Ellipse => write!(f, "ellipse"),
+            BlobShape::Irregular => write!(f, "irregular"),
+        }
+    }
+}
+
+/// Shape measures of a blob, all between 0 and 1
+pub(crate) struct ShapeMeasures {
+    /// Blob area versus minimum-area enclosing rectangle area (1 for rectangles, about 0.785 for ellipses)
+    pub(crate) rectangularity: f32,
+    /// 4 pi area / perimeter squared, using the convex hull perimeter (1 for circles)
+    pub(crate) circularity: f32,
+    /// Blob area versus convex hull area (1 for convex shapes)
+    pub(crate) convexity: f32,
+}
+
+impl fmt::Display for ShapeMeasures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rectangularity {:.2}, circularity {:.2}, convexity {:.2}",
+            self.rectangularity, self.circularity, self.convexity
+        )
+    }
+}
+
+/// Compute rectangularity, circularity and convexity of a blob
+pub(crate) fn measure(blob: &Blob) -> ShapeMeasures {
+    let hull = geometry::outline_hull(&blob.mask);
+    let area = blob.area as f32;
+    let hull_area = geometry::polygon_area(&hull);
+    let perimeter = geometry::polygon_perimeter(&hull);
+    let rect_area = geometry::min_area_rect(&hull).map_or(0.0, |rect| rect.area());
+    let ratio = |a: f32, b: f32| if b > 0.0 { (a / b).min(1.0) } else { 0.0 };
+    ShapeMeasures {
+        rectangularity: ratio(area, rect_area),
+        circularity: ratio(4.0 * PI * area, perimeter * perimeter),
+        convexity: ratio(area, hull_area),
+    }
+}
+
+/// Classify blob shape from its measures
+pub(crate) fn classify(measures: &ShapeMeasures) -> BlobShape {
+    if measures.convexity < 0.95 {
+        return BlobShape::Irregular;
+    }
+    if measures.rectangularity >= 0.9 {
+        return BlobShape::Rectangle;
+    }
+    // An ellipse fills pi/4 of its enclosing rectangle
+    if (measures.rectangularity - PI / 4.0).abs() < 0.07 || measures.circularity >= 0.9 {
+        return BlobShape::Ellipse;
+    }
+    BlobShape::Irregular
+}
@@ -0,0 +1,301 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use image::ImageBuffer;
+use image::Luma;
+use imageproc::rect::Rect;
+use imageproc::region_labelling::Connectivity;
+
+use super::extraction::Blob;
+use super::geometry;
+use super::shape::{self, BlobShape};
+
+/// Thresholds, as fractions of the peak distance, tried from the lowest when looking for item cores
+const CORE_LEVELS: [f64; 8] = [0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+/// Cores smaller than this fraction of the original blob are bumps on the outline, not items
+const MIN_CORE_FRACTION: f32 = 0.01;
+/// Parts smaller than this fraction of the original blob are not considered separate items
+const MIN_PART_FRACTION: f32 = 0.05;
+
+type LabelImage = ImageBuffer<Luma<u32>, Vec<u32>>;
+
+/// Check if the blob looks like several items merged together
+/// Solidity is blob area versus convex hull area, fill is blob area versus minimum-area rectangle area
+/// Round items never fill their rectangle, so only their solidity counts
+pub(crate) fn looks_merged(blob: &Blob, min_solidity: f32) -> bool {
+    let hull = geometry::outline_hull(&blob.mask);
+    let hull_area = geometry::polygon_area(&hull);
+    let rect_area = geometry::min_area_rect(&hull).map_or(0.0, |rect| rect.area());
+    if hull_area <= 0.0 || rect_area <= 0.0 {
+        return false;
+    }
+    let solidity = blob.area as f32 / hull_area;
+    let fill = blob.area as f32 / rect_area;
+    if solidity < min_solidity {
+        return true;
+    }
+    fill < min_solidity && shape::classify(&shape::measure(blob)) != BlobShape::Ellipse
+}
+
+/// Split a blob into separate items, using a distance-transform watershed seeded from item cores
+/// Parts are labelled from first_label up
+/// Returns None if the blob doesn't have more than one core
+pub(crate) fn split_blob(blob: &Blob, first_label: u32) -> Option<Vec<Blob>> {
+    let distances = distance_to_background(&blob.mask);
+    let peak = distances.pixels().map(|p| p[0]).fold(0.0, f64::max);
+    if peak <= 0.0 {
+        return None;
+    }
+
+    // Take the lowest level, with the largest cores, that separates several cores and keeps
+    // separating as many at the next level, as higher levels break single items into bumps
+    let min_core_area = (blob.area as f32 * MIN_CORE_FRACTION) as u32;
+    let mut previous: Option<(usize, LabelImage)> = None;
+    let mut found = None;
+    for level in CORE_LEVELS {
+        let (count, labels) = find_cores(&distances, level * level * peak, min_core_area);
+        let stable = |(previous_count, _): &(usize, LabelImage)| {
+            *previous_count >= 2 && *previous_count == count
+        };
+        if previous.as_ref().is_some_and(stable) {
+            found = previous;
+            break;
+        }
+        previous = Some((count, labels));
+    }
+    let (count, mut labels) = found?;
+
+    flood_from_cores(&mut labels, &distances);
+
+    let parts = collect_parts(blob, &labels, count, first_label);
+    let min_area = (blob.area as f32 * MIN_PART_FRACTION) as u32;
+    if parts.len() < 2 || parts.iter().any(|part| part.area < min_area) {
+        return None;
+    }
+    Some(parts)
+}
+
+/// Label the cores further than threshold (squared distance) from the background, dropping cores
+/// smaller than min_area, and return how many are left
+fn find_cores(
+    distances: &ImageBuffer<Luma<f64>, Vec<f64>>,
+    threshold: f64,
+    min_area: u32,
+) -> (usize, LabelImage) {
+    let (width, height) = distances.dimensions();
+    let cores = ImageBuffer::from_fn(width, height, |x, y| {
+        Luma([if distances.get_pixel(x, y)[0] > threshold {
+            255u8
+        } else {
+            0
+        }])
+    });
+    let mut labels =
+        imageproc::region_labelling::connected_components(&cores, Connectivity::Eight, Luma([0u8]));
+    let count = labels.pixels().map(|p| p[0]).max().unwrap_or(0) as usize;
+    let mut areas = vec![0u32; count + 1];
+    for pixel in labels.pixels() {
+        areas[pixel[0] as usize] += 1;
+    }
+    // Number the cores that are large enough from 1 up, and clear the others
+    let mut kept = 0;
+    let renumbered: Vec<u32> = areas
+        .iter()
+        .enumerate()
+        .map(|(label, &area)| {
+            if label > 0 && area >= min_area {
+                kept += 1;
+                kept
+            } else {
+                0
+            }
+        })
+        .collect();
+    for pixel in labels.pixels_mut() {
+        pixel[0] = renumbered[pixel[0] as usize];
+    }
+    (kept as usize, labels)
+}
+
+/// Squared euclidean distance from each foreground pixel to the nearest background pixel
+/// The mask is padded so pixels on the mask edge count as touching the background
+fn distance_to_background(
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+) -> ImageBuffer<Luma<f64>, Vec<f64>> {
+    let (width, height) = mask.dimensions();
+    let inverted = ImageBuffer::from_fn(width + 2, height + 2, |x, y| {
+        let inside = x > 0 && y > 0 && x <= width && y <= height;
+        if inside && mask.get_pixel(x - 1, y - 1)[0] > 0 {
+            Luma([0u8])
+        } else {
+            Luma([255u8])
+        }
+    });
+    let distances = imageproc::distance_transform::euclidean_squared_distance_transform(&inverted);
+    image::imageops::crop_imm(&distances, 1, 1, width, height).to_image()
+}
+
+/// Pixel waiting to be flooded, ordered by distance so the deepest pixels are handled first
+struct FloodPixel {
+    distance: f64,
+    x: u32,
+    y: u32,
+}
+
+impl PartialEq for FloodPixel {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl Eq for FloodPixel {}
+
+impl PartialOrd for FloodPixel {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodPixel {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+/// Grow labelled cores into the rest of the foreground, so regions meet along the narrowest part
+fn flood_from_cores(labels: &mut LabelImage, distances: &ImageBuffer<Luma<f64>, Vec<f64>>) {
+    let (width, height) = labels.dimensions();
+    let mut queue = BinaryHeap::new();
+    for (x, y, label) in labels.enumerate_pixels() {
+        if label[0] > 0 {
+            queue.push(FloodPixel {
+                distance: distances.get_pixel(x, y)[0],
+                x,
+                y,
+            });
+        }
+    }
+    while let Some(FloodPixel { x, y, .. }) = queue.pop() {
+        let label = labels.get_pixel(x, y)[0];
+        let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+        for (dx, dy) in directions {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                continue;
+            }
+            let (nx, ny) = (nx as u32, ny as u32);
+            let distance = distances.get_pixel(nx, ny)[0];
+            // Skip background and pixels already claimed by a core
+            if distance <= 0.0 || labels.get_pixel(nx, ny)[0] > 0 {
+                continue;
+            }
+            labels.put_pixel(nx, ny, Luma([label]));
+            queue.push(FloodPixel {
+                distance,
+                x: nx,
+                y: ny,
+            });
+        }
+    }
+}
+
+/// Turn each label into its own blob record, positioned relative to the original scan
+/// Parts are labelled from first_label up, and remember the component they were split from
+pub(crate) fn collect_parts(
+    blob: &Blob,
+    labels: &LabelImage,
+    count: usize,
+    first_label: u32,
+) -> Vec<Blob> {
+    let offset_x = blob.bounding_box.left() as u32;
+    let offset_y = blob.bounding_box.top() as u32;
+    let mut parts = Vec::new();
+    for label in 1..=count as u32 {
+        let pixels: Vec<(u32, u32)> = labels
+            .enumerate_pixels()
+            .filter(|(_, _, l)| l[0] == label)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        if pixels.is_empty() {
+            continue;
+        }
+        let left = pixels.iter().map(|p| p.0).min().unwrap_or(0);
+        let right = pixels.iter().map(|p| p.0).max().unwrap_or(0);
+        let top = pixels.iter().map(|p| p.1).min().unwrap_or(0);
+        let bottom = pixels.iter().map(|p| p.1).max().unwrap_or(0);
+        let mut mask = ImageBuffer::new(right - left + 1, bottom - top + 1);
+        for (x, y) in &pixels {
+            mask.put_pixel(x - left, y - top, Luma([255u8]));
+        }
+        let area = pixels.len() as u32;
+        let sum_x: u64 = pixels.iter().map(|p| p.0 as u64).sum();
+        let sum_y: u64 = pixels.iter().map(|p| p.1 as u64).sum();
+        parts.push(Blob {
+            label: first_label + parts.len() as u32,
+            parent: blob.parent.or(Some(blob.label)),
+            area,
+            bounding_box: Rect::at((offset_x + left) as i32, (offset_y + top) as i32)
+                .of_size(mask.width(), mask.height()),
+            centroid: (
+                offset_x as f32 + sum_x as f32 / area as f32,
+                offset_y as f32 + sum_y as f32 / area as f32,
+            ),
+            mask,
+        });
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use imageproc::drawing;
+
+    use super::super::extraction;
+    use super::*;
+
+    /// The only blob in a mask drawn by the given function
+    fn blob(draw: impl Fn(&mut ImageBuffer<Luma<u8>, Vec<u8>>)) -> Blob {
+        let mut mask = ImageBuffer::new(200, 150);
+        draw(&mut mask);
+        let mut blobs = extraction::extract_blobs(&mask);
+        assert_eq!(blobs.len(), 1);
+        blobs.remove(0)
+    }
+
+    fn square(mask: &mut ImageBuffer<Luma<u8>, Vec<u8>>, x: i32, y: i32, size: u32) {
+        drawing::draw_filled_rect_mut(mask, Rect::at(x, y).of_size(size, size), Luma([255u8]));
+    }
+
+    #[test]
+    fn splits_touching_squares() {
+        // Two photos overlapping at a corner
+        let blob = blob(|mask| {
+            square(mask, 10, 10, 80);
+            square(mask, 80, 50, 80);
+        });
+        assert!(looks_merged(&blob, 0.9));
+        let parts = split_blob(&blob, 7).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            parts.iter().map(|part| part.label).collect::<Vec<_>>(),
+            vec![7, 8]
+        );
+        assert!(parts.iter().all(|part| part.parent == Some(blob.label)));
+        assert_eq!(parts.iter().map(|part| part.area).sum::<u32>(), blob.area);
+        for part in &parts {
+            assert!(part.area.abs_diff(6400 - 50) < 300, "{}", part.area);
+        }
+    }
+
+    #[test]
+    fn keeps_single_items() {
+        let square = blob(|mask| square(mask, 20, 20, 100));
+        assert!(!looks_merged(&square, 0.9));
+        assert!(split_blob(&square, 7).is_none());
+        // An ellipse doesn't fill its rectangle, but is a single item
+        let ellipse =
+            blob(|mask| drawing::draw_filled_ellipse_mut(mask, (100, 75), 80, 50, Luma([255u8])));
+        assert!(!looks_merged(&ellipse, 0.9));
+        assert!(split_blob(&ellipse, 7).is_none());
+    }
+}
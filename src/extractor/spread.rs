@@ -0,0 +1,329 @@
+use std::fmt;
+
+use image::{ImageBuffer, Luma, Rgba};
+
+use super::alpha_channel;
+use super::extraction::Blob;
+use super::geometry;
+use super::shape::{self, BlobShape};
+use super::splitting;
+
+/// The gutter is searched for within this many degrees of vertical
+const MAX_GUTTER_ANGLE: f32 = 5.0;
+/// Step of the gutter angle search
+const ANGLE_STEP: f32 = 0.5;
+/// Spreads are wider than tall, like two pages side by side, width versus height within this range
+const SPREAD_ASPECT_RATIO: (f32, f32) = (1.2, 2.2);
+/// The gutter is searched for in this middle part of the blob width
+const GUTTER_RANGE: (f32, f32) = (0.4, 0.6);
+/// The fold shadow is at most this fraction of the blob width, measured where it is half as deep
+const MAX_GUTTER_WIDTH: f32 = 0.06;
+/// Brightness and texture next to the gutter are measured this far out on both sides, as fractions of the blob width
+const FLANK_RANGE: (f32, f32) = (0.025, 0.125);
+/// Paper brightness of a column is the level this fraction of its pixels are darker than, so text is left out
+const PAPER_PERCENTILE: f32 = 0.75;
+/// Profiles are smoothed over this fraction of the blob width, so single columns of ink don't count
+const SMOOTHING: f32 = 0.01;
+/// The fold shadow must be at least this much darker than the pages next to it, as a fraction of their brightness
+const MIN_SHADOW_DEPTH: f32 = 0.15;
+/// Paper brightness on the two sides of the fold may differ by at most this fraction, as they are the same paper
+const MAX_PAGE_DIFFERENCE: f32 = 0.1;
+/// The gutter may have at most this much more texture than the pages next to it, or the dark valley is
+/// ink rather than shadow
+const MAX_GUTTER_TEXTURE: f32 = 1.2;
+/// Large blobs are sampled on a grid, with no more than this many samples
+const MAX_SAMPLES: f32 = 1_000_000.0;
+/// The smaller page must have at least this fraction of the area of the larger one
+const MIN_PAGE_BALANCE: f32 = 0.8;
+
+/// Fold between the two pages of a spread, a line in scan coordinates
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Gutter {
+    /// Clockwise tilt of the fold from vertical in degrees
+    pub(crate) angle: f32,
+    /// Distance of the fold from the scan origin, measured across it
+    pub(crate) offset: f32,
+    /// How much darker the fold shadow is than the pages next to it, as a fraction of their brightness
+    pub(crate) shadow_depth: f32,
+}
+
+impl Gutter {
+    /// Signed distance of a point from the fold, negative on the left page
+    fn distance(&self, (x, y): (f32, f32)) -> f32 {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        x * cos + y * sin - self.offset
+    }
+}
+
+impl fmt::Display for Gutter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "at {:.0}, tilted {:.1} degrees, shadow depth {:.2}",
+            self.offset, self.angle, self.shadow_depth
+        )
+    }
+}
+
+/// Pixel sampled inside the blob, with its brightness and local texture (gradient magnitude)
+struct Sample {
+    x: f32,
+    y: f32,
+    luma: u8,
+    texture: f32,
+}
+
+/// Find the fold of an open booklet or album in a blob
+/// Columns across the blob, at a range of small tilts, are projected into paper brightness and texture
+/// profiles, and the fold is a deep and narrow valley in the paper near the middle, with no more text
+/// than the pages next to it, and with equally bright paper on both sides
+/// Returns None if the blob isn't shaped like a spread, or there is no fold shadow
+pub(crate) fn find_gutter(blob: &Blob, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<Gutter> {
+    // Only rectangles shaped like two pages side by side can be spreads, not round or tall items
+    if shape::classify(&shape::measure(blob)) != BlobShape::Rectangle {
+        return None;
+    }
+    let rect = geometry::min_area_rect(&geometry::outline_hull(&blob.mask))?;
+    let aspect_ratio = rect.width / rect.height;
+    if aspect_ratio < SPREAD_ASPECT_RATIO.0 || aspect_ratio > SPREAD_ASPECT_RATIO.1 {
+        return None;
+    }
+    let step = ((blob.area as f32 / MAX_SAMPLES).sqrt().floor() as usize).max(1);
+    let samples = sample_pixels(blob, image, step);
+    if samples.is_empty() {
+        return None;
+    }
+    let steps = (MAX_GUTTER_ANGLE / ANGLE_STEP) as i32;
+    (-steps..=steps)
+        .map(|angle| angle as f32 * ANGLE_STEP)
+        .filter_map(|angle| find_gutter_at(&samples, angle, step as f32))
+        .max_by(|a, b| a.shadow_depth.total_cmp(&b.shadow_depth))
+        .filter(|gutter| gutter.shadow_depth >= MIN_SHADOW_DEPTH)
+}
+
+/// Split the blob along the gutter into the left and right page, labelled first_label and the one after
+/// Returns None if the pages would be of clearly different size
+pub(crate) fn split_pages(blob: &Blob, gutter: &Gutter, first_label: u32) -> Option<(Blob, Blob)> {
+    let (left, top) = (
+        blob.bounding_box.left() as f32,
+        blob.bounding_box.top() as f32,
+    );
+    let labels = ImageBuffer::from_fn(blob.mask.width(), blob.mask.height(), |x, y| {
+        if blob.mask.get_pixel(x, y)[0] == 0 {
+            Luma([0u32])
+        } else if gutter.distance((left + x as f32, top + y as f32)) < 0.0 {
+            Luma([1])
+        } else {
+            Luma([2])
+        }
+    });
+    let mut pages = splitting::collect_parts(blob, &labels, 2, first_label).into_iter();
+    let (left_page, right_page) = (pages.next()?, pages.next()?);
+    let balance = left_page.area.min(right_page.area) as f32
+        / left_page.area.max(right_page.area).max(1) as f32;
+    if balance < MIN_PAGE_BALANCE {
+        return None;
+    }
+    Some((left_page, right_page))
+}
+
+/// Sample pixels inside the blob, away from its edge, every step pixels in both directions
+fn sample_pixels(blob: &Blob, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, step: usize) -> Vec<Sample> {
+    let inner = blob.inner_mask();
+    let (left, top) = (
+        blob.bounding_box.left() as u32,
+        blob.bounding_box.top() as u32,
+    );
+    let (width, height) = image.dimensions();
+    let luma_at =
+        |x: u32, y: u32| alpha_channel::luma(image.get_pixel(x.min(width - 1), y.min(height - 1)));
+    let mut samples = Vec::new();
+    for y in (0..inner.height()).step_by(step) {
+        for x in (0..inner.width()).step_by(step) {
+            if inner.get_pixel(x, y)[0] == 0 {
+                continue;
+            }
+            let (x, y) = (left + x, top + y);
+            let dx = luma_at(x + 1, y) - luma_at(x.saturating_sub(1), y);
+            let dy = luma_at(x, y + 1) - luma_at(x, y.saturating_sub(1));
+            samples.push(Sample {
+                x: x as f32,
+                y: y as f32,
+                luma: luma_at(x, y).round() as u8,
+                texture: dx.hypot(dy),
+            });
+        }
+    }
+    samples
+}
+
+/// Find the deepest fold shadow with columns tilted clockwise by angle degrees
+fn find_gutter_at(samples: &[Sample], angle: f32, bin_width: f32) -> Option<Gutter> {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let across = |sample: &Sample| sample.x * cos + sample.y * sin;
+    let (min, max) = samples
+        .iter()
+        .map(across)
+        .fold((f32::MAX, f32::MIN), |(min, max), u| {
+            (min.min(u), max.max(u))
+        });
+    let bins = ((max - min) / bin_width) as usize + 1;
+    let mut histograms = vec![[0u32; 256]; bins];
+    let (mut texture, mut counts) = (vec![0.0; bins], vec![0u32; bins]);
+    for sample in samples {
+        let bin = ((across(sample) - min) / bin_width) as usize;
+        histograms[bin][sample.luma as usize] += 1;
+        texture[bin] += sample.texture;
+        counts[bin] += 1;
+    }
+    let paper: Vec<Option<f32>> = histograms
+        .iter()
+        .zip(&counts)
+        .map(|(histogram, &count)| percentile(histogram, count, PAPER_PERCENTILE))
+        .collect();
+    let texture: Vec<Option<f32>> = texture
+        .iter()
+        .zip(&counts)
+        .map(|(&sum, &count)| (count > 0).then(|| sum / count as f32))
+        .collect();
+    let radius = ((bins as f32 * SMOOTHING / 2.0).round() as usize).max(1);
+    let (paper, texture) = (smooth(&paper, radius), smooth(&texture, radius));
+    let at = |fraction: f32| (bins as f32 * fraction) as usize;
+    let (near, far) = (at(FLANK_RANGE.0).max(1), at(FLANK_RANGE.1).max(2));
+    (at(GUTTER_RANGE.0)..at(GUTTER_RANGE.1).min(bins))
+        .filter(|&bin| bin >= far && bin + far < bins)
+        .filter_map(|bin| {
+            let (left_paper, left_texture) = flank_mean(&paper, &texture, bin - far..=bin - near)?;
+            let (right_paper, right_texture) =
+                flank_mean(&paper, &texture, bin + near..=bin + far)?;
+            let flank_paper = (left_paper + right_paper) / 2.0;
+            let flank_texture = (left_texture + right_texture) / 2.0;
+            let (gutter_paper, gutter_texture) = (paper[bin]?, texture[bin]?);
+            if flank_paper <= 0.0 || gutter_texture > flank_texture * MAX_GUTTER_TEXTURE {
+                return None;
+            }
+            // Both pages are the same paper, unlike the two halves of a printed photo or label
+            let page_difference = (left_paper - right_paper).abs() / left_paper.max(right_paper);
+            if page_difference > MAX_PAGE_DIFFERENCE {
+                return None;
+            }
+            // A fold shadow is a narrow line, not a dark area
+            let half_depth = (flank_paper + gutter_paper) / 2.0;
+            let in_valley = |i: &usize| paper[*i].is_some_and(|level| level < half_depth);
+            let left_end = (0..bin).rev().find(|i| !in_valley(i)).map_or(0, |i| i + 1);
+            let right_end = (bin + 1..bins).find(|i| !in_valley(i)).unwrap_or(bins);
+            if (right_end - left_end) as f32 > bins as f32 * MAX_GUTTER_WIDTH {
+                return None;
+            }
+            Some((bin, (flank_paper - gutter_paper) / flank_paper))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(bin, shadow_depth)| Gutter {
+            angle,
+            offset: min + (bin as f32 + 0.5) * bin_width,
+            shadow_depth,
+        })
+}
+
+/// Mean paper brightness and texture over a range of bins, None if there are no samples in it
+fn flank_mean(
+    paper: &[Option<f32>],
+    texture: &[Option<f32>],
+    range: std::ops::RangeInclusive<usize>,
+) -> Option<(f32, f32)> {
+    let (paper_sum, texture_sum, count) = range
+        .filter_map(|i| Some((paper[i]?, texture[i]?)))
+        .fold((0.0, 0.0, 0.0), |(p, t, n), (pi, ti)| {
+            (p + pi, t + ti, n + 1.0)
+        });
+    (count > 0.0).then(|| (paper_sum / count, texture_sum / count))
+}
+
+/// Level below which the given fraction of the counted values are, None if nothing was counted
+fn percentile(histogram: &[u32; 256], count: u32, fraction: f32) -> Option<f32> {
+    if count == 0 {
+        return None;
+    }
+    let target = (count as f32 * fraction).ceil() as u32;
+    let mut seen = 0;
+    histogram
+        .iter()
+        .position(|&n| {
+            seen += n;
+            seen >= target
+        })
+        .map(|level| level as f32)
+}
+
+/// Mean of the values within radius of each one, None where there are no values
+fn smooth(values: &[Option<f32>], radius: usize) -> Vec<Option<f32>> {
+    (0..values.len())
+        .map(|i| {
+            let window = &values[i.saturating_sub(radius)..(i + radius + 1).min(values.len())];
+            let (sum, count) = window
+                .iter()
+                .flatten()
+                .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+            (count > 0).then(|| sum / count as f32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of a 600 x 100 pixel spread, with the paper brightness given by column
+    fn samples(paper: impl Fn(f32) -> f32) -> Vec<Sample> {
+        (0..100)
+            .step_by(5)
+            .flat_map(|y| (0..600).map(move |x| (x as f32, y as f32)))
+            .map(|(x, y)| Sample {
+                x,
+                y,
+                luma: paper(x).round() as u8,
+                texture: 1.0,
+            })
+            .collect()
+    }
+
+    /// Narrow shadow centered on the given column
+    fn shadow(x: f32, center: f32) -> f32 {
+        80.0 * (-((x - center) / 6.0).powi(2)).exp()
+    }
+
+    fn is_fold(gutter: Option<Gutter>) -> bool {
+        gutter.is_some_and(|gutter| gutter.shadow_depth >= MIN_SHADOW_DEPTH)
+    }
+
+    #[test]
+    fn finds_fold_shadow() {
+        let gutter = find_gutter_at(&samples(|x| 220.0 - shadow(x, 290.0)), 0.0, 1.0).unwrap();
+        assert!((gutter.offset - 290.5).abs() <= 2.0, "{gutter}");
+        assert!(gutter.shadow_depth >= MIN_SHADOW_DEPTH, "{gutter}");
+    }
+
+    #[test]
+    fn rejects_plain_paper() {
+        assert!(!is_fold(find_gutter_at(&samples(|_| 220.0), 0.0, 1.0)));
+    }
+
+    #[test]
+    fn rejects_different_halves() {
+        // Like a printed photo with a dark line between a bright and a dark half
+        let paper = |x: f32| if x < 300.0 { 220.0 } else { 150.0 } - shadow(x, 300.0);
+        assert!(!is_fold(find_gutter_at(&samples(paper), 0.0, 1.0)));
+    }
+
+    #[test]
+    fn rejects_wide_dark_band() {
+        let paper = |x: f32| {
+            if (240.0..360.0).contains(&x) {
+                100.0
+            } else {
+                220.0
+            }
+        };
+        assert!(!is_fold(find_gutter_at(&samples(paper), 0.0, 1.0)));
+    }
+}
@@ -0,0 +1,148 @@
+use image::{GrayImage, ImageBuffer, Luma, Rgba};
+use imageproc::region_labelling::Connectivity;
+
+use super::alpha_channel;
+use super::extraction::Blob;
+
+/// Text lines are searched for within this many degrees of horizontal
+const MAX_TEXT_ANGLE: f32 = 15.0;
+/// Step of the coarse angle search, refined afterwards with a tenth of it
+const COARSE_STEP: f32 = 0.5;
+/// Fewer dark pixels than this is not considered text
+const MIN_INK_PIXELS: usize = 500;
+/// Dark spots smaller than this many pixels are noise, not glyphs
+const MIN_GLYPH_AREA: u32 = 4;
+/// Dark spots wider or taller than this fraction of the short side of the blob are pictures or
+/// shadows, not glyphs
+const MAX_GLYPH_FRACTION: f32 = 0.05;
+/// At most this many dark pixels are projected, as every pixel is projected at every angle
+const MAX_INK_PIXELS: usize = 200_000;
+
+/// Text line angle and the confidence (0-1) that it is one
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TextLines {
+    /// Clockwise rotation of the text lines in degrees
+    pub(crate) angle: f32,
+    pub(crate) confidence: f32,
+}
+
+/// Estimate the angle of the text lines in a blob using projection profiles
+/// Dark pixels are projected onto rows for each candidate angle, and text lines give the sharpest
+/// profile (largest sum of squared row counts) when the rows follow them
+pub(crate) fn estimate(blob: &Blob, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<TextLines> {
+    let ink = ink_pixels(blob, image);
+    if ink.len() < MIN_INK_PIXELS {
+        return None;
+    }
+    let steps = (MAX_TEXT_ANGLE / COARSE_STEP) as i32;
+    let scores: Vec<(f32, f64)> = (-steps..=steps)
+        .map(|step| step as f32 * COARSE_STEP)
+        .map(|angle| (angle, profile_sharpness(&ink, angle)))
+        .collect();
+    let mean = scores.iter().map(|(_, score)| score).sum::<f64>() / scores.len() as f64;
+    let (coarse, _) = scores.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))?;
+    let fine_step = COARSE_STEP / 10.0;
+    let (angle, best) = (-10..=10)
+        .map(|step| coarse + step as f32 * fine_step)
+        .map(|angle| (angle, profile_sharpness(&ink, angle)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if best <= 0.0 {
+        return None;
+    }
+    Some(TextLines {
+        angle,
+        confidence: (1.0 - mean / best).clamp(0.0, 1.0) as f32,
+    })
+}
+
+/// Pixels of glyph-sized dark spots inside the blob, away from its edge, in scan coordinates
+/// Dark means at or below the Otsu threshold of the pixels inside the blob, and large dark areas,
+/// like the dark half of a photo, are left out so they don't drown the text
+fn ink_pixels(blob: &Blob, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<(f32, f32)> {
+    // Dark pixels near the blob edge are more likely shadows than text
+    let inner = blob.inner_mask();
+    let (left, top) = (
+        blob.bounding_box.left() as u32,
+        blob.bounding_box.top() as u32,
+    );
+    let pixels: Vec<(u32, u32, u8)> = inner
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] > 0)
+        .map(|(x, y, _)| {
+            let luma = alpha_channel::luma(image.get_pixel(left + x, top + y)) as u8;
+            (left + x, top + y, luma)
+        })
+        .collect();
+    // Threshold only the pixels inside the blob, by laying them out as a single row
+    let levels: Vec<u8> = pixels.iter().map(|&(_, _, luma)| luma).collect();
+    let Some(row) = GrayImage::from_raw(levels.len() as u32, 1, levels) else {
+        return Vec::new();
+    };
+    if row.width() == 0 {
+        return Vec::new();
+    }
+    let level = imageproc::contrast::otsu_level(&row);
+    let mut dark = GrayImage::new(inner.width(), inner.height());
+    for &(x, y, luma) in &pixels {
+        if luma <= level {
+            dark.put_pixel(x - left, y - top, Luma([255u8]));
+        }
+    }
+    let (labels, glyphs) = glyph_labels(&dark);
+    let ink: Vec<(f32, f32)> = pixels
+        .into_iter()
+        .filter(|&(x, y, _)| glyphs[labels.get_pixel(x - left, y - top)[0] as usize])
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    // Every nth pixel still shows the same lines, at a fraction of the cost
+    let stride = ink.len().div_ceil(MAX_INK_PIXELS).max(1);
+    ink.into_iter().step_by(stride).collect()
+}
+
+/// Label the dark spots, and tell for each label whether the spot is the size of a glyph
+fn glyph_labels(dark: &GrayImage) -> (ImageBuffer<Luma<u32>, Vec<u32>>, Vec<bool>) {
+    let labels =
+        imageproc::region_labelling::connected_components(dark, Connectivity::Eight, Luma([0u8]));
+    let count = labels.pixels().map(|p| p[0]).max().unwrap_or(0) as usize;
+    // Area and bounds (left, top, right, bottom) of each spot
+    let mut spots = vec![(0u32, u32::MAX, u32::MAX, 0u32, 0u32); count + 1];
+    for (x, y, label) in labels.enumerate_pixels() {
+        let spot = &mut spots[label[0] as usize];
+        *spot = (
+            spot.0 + 1,
+            spot.1.min(x),
+            spot.2.min(y),
+            spot.3.max(x),
+            spot.4.max(y),
+        );
+    }
+    let max_size = (dark.width().min(dark.height()) as f32 * MAX_GLYPH_FRACTION) as u32;
+    let glyphs = spots
+        .iter()
+        .enumerate()
+        .map(|(label, &(area, left, top, right, bottom))| {
+            label > 0
+                && area >= MIN_GLYPH_AREA
+                && right - left < max_size
+                && bottom - top < max_size
+        })
+        .collect();
+    (labels, glyphs)
+}
+
+/// Sum of squared row counts, with rows running at the given clockwise angle
+fn profile_sharpness(ink: &[(f32, f32)], angle: f32) -> f64 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let rows: Vec<i64> = ink
+        .iter()
+        .map(|&(x, y)| (-x * sin + y * cos).floor() as i64)
+        .collect();
+    let (Some(min), Some(max)) = (rows.iter().min(), rows.iter().max()) else {
+        return 0.0;
+    };
+    let mut counts = vec![0u64; (max - min + 1) as usize];
+    for row in &rows {
+        counts[(row - min) as usize] += 1;
+    }
+    counts.iter().map(|&count| (count * count) as f64).sum()
+}
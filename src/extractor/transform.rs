@@ -0,0 +1,233 @@
+use std::fmt;
+
+/// Projective transform of points from one image to another, as a row-major 3x3 matrix
+/// Pixel centers are at whole coordinates, like in imageproc
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Transform(pub(crate) [[f64; 3]; 3]);
+
+impl Transform {
+    pub(crate) fn identity() -> Self {
+        Transform([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    pub(crate) fn translate(dx: f32, dy: f32) -> Self {
+        Transform([
+            [1.0, 0.0, dx as f64],
+            [0.0, 1.0, dy as f64],
+            [0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub(crate) fn scale(sx: f32, sy: f32) -> Self {
+        Transform([
+            [sx as f64, 0.0, 0.0],
+            [0.0, sy as f64, 0.0],
+            [0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Clockwise rotation in degrees around the origin, the same direction imageproc rotates images
+    pub(crate) fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+        Transform([[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    /// Clockwise rotation by a multiple of 90 degrees of a whole image of the given size
+    pub(crate) fn quarter_turn(rotation: u32, width: u32, height: u32) -> Self {
+        let (width, height) = (width as f32, height as f32);
+        let rotation = rotation % 360;
+        let offset = match rotation {
+            90 => Transform::translate(height - 1.0, 0.0),
+            180 => Transform::translate(width - 1.0, height - 1.0),
+            270 => Transform::translate(0.0, width - 1.0),
+            _ => Transform::identity(),
+        };
+        Transform::rotate(rotation as f32).then(&offset)
+    }
+
+    /// Map a quadrilateral, with corners ordered top-left, top-right, bottom-right, bottom-left,
+    /// onto a width x height image, with the corners on the outer edges of the corner pixels
+    /// Returns None if three of the corners are on a line
+    pub(crate) fn from_quad_to_rect(
+        corners: [(f32, f32); 4],
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        // Map the unit square onto the quadrilateral (Heckbert), then turn that around
+        let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = corners.map(|(x, y)| (x as f64, y as f64));
+        let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+        let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+        let denominator = dx1 * dy2 - dx2 * dy1;
+        if denominator == 0.0 {
+            return None;
+        }
+        let g = (dx3 * dy2 - dx2 * dy3) / denominator;
+        let h = (dx1 * dy3 - dx3 * dy1) / denominator;
+        let square_to_quad = Transform([
+            [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+            [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+            [g, h, 1.0],
+        ]);
+        Some(
+            square_to_quad
+                .invert()?
+                .then(&Transform::scale(width as f32, height as f32))
+                .then(&Transform::translate(-0.5, -0.5)),
+        )
+    }
+
+    /// Apply this transform first, then the next one
+    pub(crate) fn then(&self, next: &Transform) -> Transform {
+        let (a, b) = (next.0, self.0);
+        let mut product = [[0.0; 3]; 3];
+        for (row, values) in product.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+            }
+        }
+        Transform(product)
+    }
+
+    /// Transform a point
+    pub(crate) fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let m = &self.0;
+        let (x, y) = (x as f64, y as f64);
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        (
+            ((m[0][0] * x + m[0][1] * y + m[0][2]) / w) as f32,
+            ((m[1][0] * x + m[1][1] * y + m[1][2]) / w) as f32,
+        )
+    }
+
+    /// Transform going the other way, None if it collapses points onto a line
+    pub(crate) fn invert(&self) -> Option<Transform> {
+        let m = &self.0;
+        let cofactor = |row: usize, column: usize| {
+            let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+            let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let determinant = (0..3)
+            .map(|column| m[0][column] * cofactor(0, column))
+            .sum::<f64>();
+        if determinant.abs() < f64::EPSILON {
+            return None;
+        }
+        let mut inverse = [[0.0; 3]; 3];
+        for (row, values) in inverse.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                // Adjugate is the transposed cofactor matrix
+                *value = cofactor(column, row) / determinant;
+            }
+        }
+        Some(Transform(inverse))
+    }
+
+    /// Same transform, scaled so the bottom right element is 1
+    pub(crate) fn normalized(&self) -> Transform {
+        let scale = self.0[2][2];
+        if scale == 0.0 {
+            return *self;
+        }
+        // Adding zero turns negative zeros positive, so they print without a sign
+        Transform(self.0.map(|row| row.map(|value| value / scale + 0.0)))
+    }
+
+    /// Check if the transform keeps parallel lines parallel, so there is no perspective in it
+    pub(crate) fn is_affine(&self) -> bool {
+        let m = self.normalized().0;
+        m[2][0].abs() < 1e-9 && m[2][1].abs() < 1e-9
+    }
+
+    /// Outline of a whole image of the given size moved by this transform, following the pixel edges
+    /// Corners are ordered top-left, top-right, bottom-right, bottom-left
+    pub(crate) fn image_outline(&self, width: u32, height: u32) -> [(f32, f32); 4] {
+        let (right, bottom) = (width as f32 - 0.5, height as f32 - 0.5);
+        [(-0.5, -0.5), (right, -0.5), (right, bottom), (-0.5, bottom)].map(|p| self.apply(p))
+    }
+
+    /// JSON array of the rows of the normalized matrix
+    pub(crate) fn to_json(self) -> String {
+        let rows = self
+            .normalized()
+            .0
+            .map(|row| format!("[{:.6}, {:.6}, {:.6}]", row[0], row[1], row[2]));
+        format!("[{}]", rows.join(", "))
+    }
+}
+
+impl fmt::Display for Transform {
+    /// Rows of the normalized matrix separated by semicolons
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = self
+            .normalized()
+            .0
+            .map(|row| format!("{:.6} {:.6} {:.6}", row[0], row[1], row[2]));
+        write!(f, "{}", rows.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        let distance = (actual.0 - expected.0).hypot(actual.1 - expected.1);
+        assert!(distance < 1e-3, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn applies_in_order() {
+        let transform = Transform::translate(1.0, 2.0).then(&Transform::scale(2.0, 3.0));
+        assert_near(transform.apply((1.0, 1.0)), (4.0, 9.0));
+        // Clockwise with y pointing down
+        assert_near(Transform::rotate(90.0).apply((1.0, 0.0)), (0.0, 1.0));
+    }
+
+    #[test]
+    fn inverts() {
+        let transform = Transform::rotate(30.0)
+            .then(&Transform::scale(2.0, 0.5))
+            .then(&Transform::translate(5.0, -3.0));
+        let inverse = transform.invert().unwrap();
+        assert_near(inverse.apply(transform.apply((12.0, 34.0))), (12.0, 34.0));
+        assert!(Transform::scale(0.0, 1.0).invert().is_none());
+    }
+
+    #[test]
+    fn quarter_turns_map_image_corners() {
+        // A 10x5 image, its top-left pixel goes to the corner the rotation brings it to
+        assert_near(
+            Transform::quarter_turn(90, 10, 5).apply((0.0, 0.0)),
+            (4.0, 0.0),
+        );
+        assert_near(
+            Transform::quarter_turn(90, 10, 5).apply((9.0, 4.0)),
+            (0.0, 9.0),
+        );
+        assert_near(
+            Transform::quarter_turn(180, 10, 5).apply((0.0, 0.0)),
+            (9.0, 4.0),
+        );
+        assert_near(
+            Transform::quarter_turn(270, 10, 5).apply((0.0, 0.0)),
+            (0.0, 9.0),
+        );
+        assert_near(
+            Transform::quarter_turn(360, 10, 5).apply((3.0, 2.0)),
+            (3.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn maps_quad_corners_to_pixel_edges() {
+        let corners = [(10.0, 20.0), (50.0, 25.0), (45.0, 60.0), (5.0, 55.0)];
+        let transform = Transform::from_quad_to_rect(corners, 40, 30).unwrap();
+        let expected = [(-0.5, -0.5), (39.5, -0.5), (39.5, 29.5), (-0.5, 29.5)];
+        for (corner, expected) in corners.into_iter().zip(expected) {
+            assert_near(transform.apply(corner), expected);
+        }
+        let collinear = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (0.0, 5.0)];
+        assert!(Transform::from_quad_to_rect(collinear, 4, 4).is_none());
+    }
+}
@@ -7,6 +7,14 @@ use image::Rgba;
 use wild::ArgsOs;
 
 use extractor::BlobExtractor;
+use extractor::contours::ContourFormat;
+use extractor::deskew::DeskewMethod;
+use extractor::ellipse::EllipseCanvas;
+use extractor::layout::Canvas;
+use extractor::length::Length;
+use extractor::morphology::Program;
+use extractor::ordering::{BlobOrder, Corner};
+use extractor::overhang::OverhangPolicy;
 
 mod arg_validators;
 mod error;
@@ -24,24 +32,140 @@ struct Args {
     /// Floodfill fuzz (euclidean distance)
     #[arg(short('f'), long, default_value_t = 17.0)]
     floodfill_fuzz: f32,
-    /// Trim edges (pixels)
-    #[arg(short('t'), long, default_value_t = 10)]
-    trim_edges: u8,
-    /// Grow edges (pixels)
-    #[arg(short('g'), long, default_value_t = 6)]
-    grow_edges: u8,
-    /// Blur edge factor
-    #[arg(short('b'), long, default_value_t = 2.0, value_parser = arg_validators::validate_blur_edge_factor)]
-    blur_edge_factor: f32,
-    /// Minimum pixels touching detected line
-    #[arg(short('p'), long, default_value_t = 225)]
-    min_pixels_touching_line: u32,
+    /// Trim edges (pixels, or millimeters like 0.8mm)
+    #[arg(short('t'), long, default_value = "10", value_parser = arg_validators::validate_length)]
+    trim_edges: Length,
+    /// Grow edges (pixels, or millimeters like 0.5mm)
+    #[arg(short('g'), long, default_value = "6", value_parser = arg_validators::validate_length)]
+    grow_edges: Length,
+    /// Morphology program for mask cleanup, replaces trim and grow edges (e.g. erode:disk:1,blur:3,dilate:disk:3,threshold:80%)
+    #[arg(short('m'), long, value_parser = arg_validators::validate_morphology_program)]
+    morphology: Option<Program>,
+    /// Blur edge factor (pixels, or millimeters like 0.3mm)
+    #[arg(short('b'), long, default_value = "2", value_parser = arg_validators::validate_blur_edge_factor)]
+    blur_edge_factor: Length,
+    /// Minimum pixels touching detected line (pixels, or millimeters like 38mm)
+    #[arg(short('p'), long, default_value = "225", value_parser = arg_validators::validate_length)]
+    min_pixels_touching_line: Length,
+    /// Minimum distance between detected lines (pixels, or millimeters like 8mm)
+    #[arg(long, default_value = "50", value_parser = arg_validators::validate_length)]
+    line_suppression_radius: Length,
     /// Maximum detected lines
     #[arg(short('l'), long, default_value_t = 4)]
     max_lines: usize,
-    /// Maximum blob rotation
-    #[arg(short('r'), long, default_value_t = 10.0)]
+    /// Deskew method
+    #[arg(long, value_enum, default_value_t = DeskewMethod::Hough)]
+    deskew_method: DeskewMethod,
+    /// Minimum deskew confidence (0-1), below this the other method or no rotation is used
+    #[arg(long, default_value_t = 0.5, value_parser = arg_validators::validate_fraction)]
+    min_deskew_confidence: f32,
+    /// Fall back to the angle of text lines when no edge-based deskew is confident enough, for document scans
+    #[arg(long, default_value_t = false)]
+    text_deskew: bool,
+    /// Maximum blob rotation (degrees), larger deskew angles are ignored. Deskew angles are folded
+    /// into (-45, 45], so the default means no limit and --min-deskew-confidence rejects unreliable angles
+    #[arg(short('r'), long, default_value_t = 45.0)]
     max_blob_rotation: f32,
+    /// Fit an ellipse to round blobs, turn them upright by its axes and give them a smooth elliptical edge
+    #[arg(long, default_value_t = false)]
+    fit_ellipse: bool,
+    /// Canvas size for fitted ellipses
+    #[arg(long, value_enum, default_value_t = EllipseCanvas::Tight)]
+    ellipse_canvas: EllipseCanvas,
+    /// Draw the alpha channel of rectangular blobs from their fitted outline, giving crisp straight edges
+    #[arg(long, default_value_t = false)]
+    analytic_mask: bool,
+    /// Edge feather width of analytic masks (pixels, or millimeters like 0.2mm)
+    #[arg(long, default_value = "1", value_parser = arg_validators::validate_positive_length)]
+    feather: Length,
+    /// Warp four-sided blobs to rectangles, correcting perspective instead of only rotating them
+    #[arg(long, default_value_t = false)]
+    perspective_correction: bool,
+    /// Crop blobs to the largest rectangle inside their opaque part, removing soft edges and corners
+    #[arg(long, default_value_t = false)]
+    inner_crop: bool,
+    /// Extra margin removed on every side by the inner crop (pixels)
+    #[arg(long, default_value_t = 0)]
+    inner_crop_inset: u32,
+    /// Turn blobs with sideways or upside down text the right way up, using Tesseract's orientation
+    /// detection, which needs osd.traineddata in the tessdata folder
+    #[arg(long, default_value_t = false)]
+    auto_orient: bool,
+    /// Split blobs that look like several touching items
+    #[arg(long, default_value_t = false)]
+    split_touching: bool,
+    /// Minimum solidity and rectangle fill (0-1) before a blob is considered merged items, round blobs only need the solidity
+    #[arg(long, default_value_t = 0.9, value_parser = arg_validators::validate_fraction)]
+    split_solidity: f32,
+    /// Split blobs that are open booklets or albums into left and right pages at the fold
+    #[arg(long, default_value_t = false)]
+    split_spreads: bool,
+    /// Merge blobs whose bounding boxes overlap or are within this gap (pixels)
+    #[arg(long)]
+    merge_gap: Option<u32>,
+    /// Only merge blobs if the result fills at least this fraction (0-1) of its enclosing rectangle
+    #[arg(long, value_parser = arg_validators::validate_fraction)]
+    merge_min_fill: Option<f32>,
+    /// Minimum blob area (pixels)
+    #[arg(long)]
+    min_blob_area: Option<u32>,
+    /// Maximum blob area (pixels)
+    #[arg(long)]
+    max_blob_area: Option<u32>,
+    /// Minimum blob size, shortest side (millimeters)
+    #[arg(long, value_parser = arg_validators::validate_blob_size)]
+    min_blob_size: Option<f32>,
+    /// Maximum blob size, longest side (millimeters)
+    #[arg(long, value_parser = arg_validators::validate_blob_size)]
+    max_blob_size: Option<f32>,
+    /// Minimum blob aspect ratio (long side / short side)
+    #[arg(long, value_parser = arg_validators::validate_aspect_ratio)]
+    min_aspect_ratio: Option<f32>,
+    /// Maximum blob aspect ratio (long side / short side)
+    #[arg(long, value_parser = arg_validators::validate_aspect_ratio)]
+    max_aspect_ratio: Option<f32>,
+    /// What to do with blobs that extend past the edge of the scan
+    #[arg(long, value_enum, default_value_t = OverhangPolicy::Flag)]
+    overhang_policy: OverhangPolicy,
+    /// Blobs within this distance of the scan edge are considered overhanging (pixels, or millimeters like 1mm), defaults to the trim edges distance plus 2 pixels
+    #[arg(long, value_parser = arg_validators::validate_length)]
+    overhang_margin: Option<Length>,
+    /// Blob numbering order
+    #[arg(long, value_enum, default_value_t = BlobOrder::Label)]
+    blob_order: BlobOrder,
+    /// Distance between blob centers still considered the same row or column (pixels), defaults to half the median blob size
+    #[arg(long)]
+    row_tolerance: Option<u32>,
+    /// Corner used by the corner blob order
+    #[arg(long, value_enum, default_value_t = Corner::TopLeft)]
+    order_corner: Corner,
+    /// Crop blobs to the aspect ratio of the nearest standard print format, like 10x15 cm
+    #[arg(long, default_value_t = false)]
+    snap_print_size: bool,
+    /// Largest difference on either side for snapping to a print format (millimeters)
+    #[arg(long, default_value_t = 3.0, value_parser = arg_validators::validate_blob_size)]
+    snap_tolerance: f32,
+    /// Margin around each item in the output (pixels, or millimeters like 5mm)
+    #[arg(long, default_value = "0", value_parser = arg_validators::validate_length)]
+    padding: Length,
+    /// Center each item on a canvas of fixed size (e.g. 1200x800) or fixed aspect ratio (e.g. 3:2)
+    #[arg(long, value_parser = arg_validators::validate_canvas)]
+    canvas: Option<Canvas>,
+    /// Background color of padding and canvas, transparent if not set
+    #[arg(long, value_parser = arg_validators::validate_color)]
+    background_color: Option<Rgba<u8>>,
+    /// Add a drop shadow below each item, offset by this distance (pixels, or millimeters like 2mm)
+    #[arg(long, value_parser = arg_validators::validate_positive_length)]
+    drop_shadow: Option<Length>,
+    /// Export the outline of each blob as a simplified polygon, in scan and output image coordinates
+    #[arg(long, value_enum)]
+    export_contours: Option<ContourFormat>,
+    /// Largest distance between the traced outline and the simplified polygon (pixels, or millimeters like 0.2mm)
+    #[arg(long, default_value = "1", value_parser = arg_validators::validate_positive_length)]
+    contour_tolerance: Length,
+    /// Save the transform from output image to scan coordinates of each blob as JSON, it is always added to the image metadata
+    #[arg(long, default_value_t = false)]
+    export_transform: bool,
     /// Output image pixel density in inches
     #[arg(short('d'), long, default_value_t = 150)]
     dpi: u32,